@@ -39,7 +39,7 @@
 
               # Create nix.conf with experimental features enabled
               mkdir -p "$NIX_CONF_DIR"
-              echo "experimental-features = ca-derivations flakes" > "$NIX_CONF_DIR/nix.conf"
+              echo "experimental-features = ca-derivations dynamic-derivations flakes git-hashing" > "$NIX_CONF_DIR/nix.conf"
 
               # Init ahead of time, because concurrent initialization is flaky
               ${cfg.nixPackage}/bin/nix-store --init
@@ -58,6 +58,13 @@
             inherits = "release"
             EOF
           '';
+          addDerivationDataProfile = ''
+            cat >> Cargo.toml <<'EOF'
+
+            [profile.derivation-data]
+            inherits = "release"
+            EOF
+          '';
         in
         {
           profiles.harmonia = {
@@ -67,6 +74,30 @@
             depsDrvConfig.mkDerivation.postPatch = addHarmoniaProfile;
             drvConfig.mkDerivation.postPatch = addHarmoniaProfile;
           };
+          profiles.derivation-data = {
+            features = [ "derivation-data" ];
+            runTests = true;
+            depsDrvConfig.mkDerivation.postPatch = addDerivationDataProfile;
+            drvConfig.mkDerivation.postPatch = addDerivationDataProfile;
+          };
+        };
+      nci.crates.nix-bindings-expr =
+        let
+          addSerdeProfile = ''
+            cat >> Cargo.toml <<'EOF'
+
+            [profile.serde]
+            inherits = "release"
+            EOF
+          '';
+        in
+        {
+          profiles.serde = {
+            features = [ "serde" ];
+            runTests = true;
+            depsDrvConfig.mkDerivation.postPatch = addSerdeProfile;
+            drvConfig.mkDerivation.postPatch = addSerdeProfile;
+          };
         };
     };
 }
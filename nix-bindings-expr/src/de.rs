@@ -0,0 +1,902 @@
+//! # Deserializing Nix values with serde
+//!
+//! This module implements [`serde::Deserializer`] for Nix [`Value`]s, so that evaluated Nix data can be turned into Rust types that implement [`serde::Deserialize`].
+//!
+//! Requires the `serde` feature.
+//!
+//! Values are forced as the Rust type is traversed.
+//! Only the attributes that are fields of a struct are evaluated.
+//!
+//! Errors report the location of the offending value, e.g. `services.nginx.port: expected int, got string`.
+//!
+//! ## Type mapping
+//!
+//! - `bool`, integers, floats and strings map to the corresponding Nix types.
+//!   Floats also accept Nix integers.
+//!   Strings also accept Nix paths; the [string context](https://nix.dev/manual/nix/latest/language/string-context.html) is ignored.
+//! - [`Option`] is `None` for `null` and for missing attributes.
+//! - Sequences and tuples map to lists.
+//! - Maps and structs map to attribute sets.
+//!   For structs, attributes that are not fields are ignored and not evaluated, so `#[serde(deny_unknown_fields)]` has no effect.
+//! - Unit enum variants map to strings, and other enum variants to an attribute set with a single attribute named after the variant.
+//!
+//! ## Example
+//!
+//! ```rust
+//! # use nix_bindings_expr::eval_state::{EvalState, test_init, gc_register_my_thread};
+//! # use nix_bindings_store::store::Store;
+//! # use std::collections::HashMap;
+//! #[derive(serde::Deserialize)]
+//! struct Nginx {
+//!     enable: bool,
+//!     port: u16,
+//!     root: Option<String>,
+//! }
+//!
+//! # fn example() -> anyhow::Result<()> {
+//! # test_init();
+//! # let guard = gc_register_my_thread()?;
+//! let store = Store::open(None, HashMap::new())?;
+//! let mut es = EvalState::new(store, [])?;
+//!
+//! let v = es.eval_from_string("{ enable = true; port = 80; other = throw \"unused\"; }", "<example>")?;
+//! let nginx: Nginx = nix_bindings_expr::de::from_value(&mut es, &v)?;
+//! assert!(nginx.enable);
+//! assert_eq!(nginx.port, 80);
+//! assert_eq!(nginx.root, None);
+//! # drop(guard);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::eval_state::{AttrsIter, EvalState};
+use crate::show::is_identifier;
+use crate::value::{Value, ValueType};
+use serde::de::{self, DeserializeOwned, DeserializeSeed, IntoDeserializer, Visitor};
+use std::fmt;
+
+/// Deserializes a Rust value from a Nix value.
+///
+/// See the [module documentation][`self`] for details.
+pub fn from_value<T>(eval_state: &mut EvalState, value: &Value) -> Result<T, Error>
+where
+    T: DeserializeOwned,
+{
+    T::deserialize(Deserializer::new(eval_state, value.clone()))
+}
+
+/// An error that occurred while deserializing a Nix value, or while [serializing][`crate::ser`] a Rust value to a Nix value.
+///
+/// If the error was caused by a Nix evaluation error, that error is available as the [`source`][`std::error::Error::source`].
+#[derive(Debug)]
+pub struct Error {
+    path: Option<String>,
+    msg: String,
+    source: Option<anyhow::Error>,
+}
+impl Error {
+    pub(crate) fn eval(path: &AttrPath, e: anyhow::Error) -> Self {
+        Error {
+            path: Some(path.to_string()),
+            msg: e.to_string(),
+            source: Some(e),
+        }
+    }
+
+    fn at(path: &AttrPath, msg: impl fmt::Display) -> Self {
+        Error {
+            path: Some(path.to_string()),
+            msg: msg.to_string(),
+            source: None,
+        }
+    }
+
+    /// Sets the location of the error, unless it is already known.
+    pub(crate) fn or_at(mut self, path: &AttrPath) -> Self {
+        if self.path.is_none() {
+            self.path = Some(path.to_string());
+        }
+        self
+    }
+
+    /// The location of the value that could not be deserialized or serialized, such as `services.nginx.port`.
+    ///
+    /// This is empty for the value passed to [`from_value`] or [`to_value`][`crate::ser::to_value`] itself.
+    pub fn path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// The error message, without the location.
+    pub fn msg(&self) -> &str {
+        &self.msg
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.path.as_deref() {
+            Some(path) if !path.is_empty() => write!(f, "{}: {}", path, self.msg),
+            _ => f.write_str(&self.msg),
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.source
+            .as_ref()
+            .map(|e| e.as_ref() as &(dyn std::error::Error + 'static))
+    }
+}
+impl de::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        Error {
+            path: None,
+            msg: msg.to_string(),
+            source: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Segment {
+    Attr(String),
+    Index(u32),
+}
+
+/// The location of a value relative to the root value, for error messages.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct AttrPath(Vec<Segment>);
+impl AttrPath {
+    pub(crate) fn attr(&self, name: &str) -> Self {
+        let mut p = self.clone();
+        p.0.push(Segment::Attr(name.to_owned()));
+        p
+    }
+    pub(crate) fn index(&self, i: u32) -> Self {
+        let mut p = self.clone();
+        p.0.push(Segment::Index(i));
+        p
+    }
+}
+impl fmt::Display for AttrPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, segment) in self.0.iter().enumerate() {
+            match segment {
+                Segment::Attr(name) => {
+                    if i > 0 {
+                        f.write_str(".")?;
+                    }
+                    if is_identifier(name) {
+                        f.write_str(name)?;
+                    } else {
+                        write!(f, "{:?}", name)?;
+                    }
+                }
+                Segment::Index(i) => write!(f, "[{}]", i)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The type name as used in error messages.
+pub(crate) fn type_name(t: &ValueType) -> &'static str {
+    match t {
+        ValueType::AttrSet => "attrset",
+        ValueType::Bool => "bool",
+        ValueType::External => "external",
+        ValueType::Float => "float",
+        ValueType::Function => "function",
+        ValueType::Int => "int",
+        ValueType::List => "list",
+        ValueType::Null => "null",
+        ValueType::Path => "path",
+        ValueType::String => "string",
+        ValueType::Unknown => "unknown",
+    }
+}
+
+/// A [`serde::Deserializer`] for a Nix [`Value`].
+///
+/// Most callers will want to use [`from_value`] instead.
+pub struct Deserializer<'a> {
+    eval_state: &'a mut EvalState,
+    value: Value,
+    path: AttrPath,
+}
+impl<'a> Deserializer<'a> {
+    /// Creates a deserializer for `value`.
+    pub fn new(eval_state: &'a mut EvalState, value: Value) -> Self {
+        Deserializer {
+            eval_state,
+            value,
+            path: AttrPath::default(),
+        }
+    }
+
+    /// Forces the value and returns its type.
+    fn value_type(&mut self) -> Result<ValueType, Error> {
+        self.eval_state
+            .value_type(&self.value)
+            .map_err(|e| Error::eval(&self.path, e))
+    }
+
+    fn expect(&mut self, expected: ValueType) -> Result<(), Error> {
+        let t = self.value_type()?;
+        if t != expected {
+            return Err(self.invalid_type(&t, type_name(&expected)));
+        }
+        Ok(())
+    }
+
+    fn invalid_type(&self, t: &ValueType, expected: &str) -> Error {
+        Error::at(
+            &self.path,
+            format_args!("expected {}, got {}", expected, type_name(t)),
+        )
+    }
+
+    fn eval_err(&self, e: anyhow::Error) -> Error {
+        Error::eval(&self.path, e)
+    }
+
+    fn get_string(&mut self) -> Result<String, Error> {
+        match self.value_type()? {
+            ValueType::String => self
+                .eval_state
+                .require_string(&self.value)
+                .map_err(|e| self.eval_err(e)),
+            ValueType::Path => self
+                .eval_state
+                .require_path(&self.value)
+                .map_err(|e| self.eval_err(e)),
+            t => Err(self.invalid_type(&t, "string")),
+        }
+    }
+
+    fn deserialize_int<'de, V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        self.expect(ValueType::Int)?;
+        let i = self
+            .eval_state
+            .require_int(&self.value)
+            .map_err(|e| self.eval_err(e))?;
+        visitor.visit_i64(i).map_err(|e: Error| e.or_at(&self.path))
+    }
+
+    fn deserialize_float<'de, V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        let f = match self.value_type()? {
+            ValueType::Float => self
+                .eval_state
+                .require_float(&self.value)
+                .map_err(|e| self.eval_err(e))?,
+            ValueType::Int => self
+                .eval_state
+                .require_int(&self.value)
+                .map_err(|e| self.eval_err(e))? as f64,
+            t => return Err(self.invalid_type(&t, "float")),
+        };
+        visitor.visit_f64(f).map_err(|e: Error| e.or_at(&self.path))
+    }
+
+    fn seq_access(&mut self) -> Result<SeqAccess<'_>, Error> {
+        self.expect(ValueType::List)?;
+        let len = self
+            .eval_state
+            .require_list_size(&self.value)
+            .map_err(|e| self.eval_err(e))?;
+        Ok(SeqAccess {
+            eval_state: self.eval_state,
+            list: self.value.clone(),
+            path: &self.path,
+            next: 0,
+            len,
+        })
+    }
+}
+
+impl<'de> de::Deserializer<'de> for Deserializer<'_> {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        match self.value_type()? {
+            ValueType::Bool => self.deserialize_bool(visitor),
+            ValueType::Int => self.deserialize_int(visitor),
+            ValueType::Float => self.deserialize_float(visitor),
+            ValueType::String | ValueType::Path => self.deserialize_string(visitor),
+            ValueType::Null => self.deserialize_unit(visitor),
+            ValueType::List => self.deserialize_seq(visitor),
+            ValueType::AttrSet => self.deserialize_map(visitor),
+            t => Err(Error::at(
+                &self.path,
+                format_args!("cannot deserialize a {}", type_name(&t)),
+            )),
+        }
+    }
+
+    fn deserialize_bool<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        self.expect(ValueType::Bool)?;
+        let b = self
+            .eval_state
+            .require_bool(&self.value)
+            .map_err(|e| self.eval_err(e))?;
+        visitor
+            .visit_bool(b)
+            .map_err(|e: Error| e.or_at(&self.path))
+    }
+
+    fn deserialize_i8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_int(visitor)
+    }
+    fn deserialize_i16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_int(visitor)
+    }
+    fn deserialize_i32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_int(visitor)
+    }
+    fn deserialize_i64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_int(visitor)
+    }
+    fn deserialize_u8<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_int(visitor)
+    }
+    fn deserialize_u16<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_int(visitor)
+    }
+    fn deserialize_u32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_int(visitor)
+    }
+    fn deserialize_u64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_int(visitor)
+    }
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_float(visitor)
+    }
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_float(visitor)
+    }
+
+    fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+    fn deserialize_string<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        let s = self.get_string()?;
+        visitor
+            .visit_string(s)
+            .map_err(|e: Error| e.or_at(&self.path))
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_byte_buf(visitor)
+    }
+    fn deserialize_byte_buf<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        let s = self.get_string()?;
+        visitor
+            .visit_byte_buf(s.into_bytes())
+            .map_err(|e: Error| e.or_at(&self.path))
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        if self.value_type()? == ValueType::Null {
+            visitor.visit_none().map_err(|e: Error| e.or_at(&self.path))
+        } else {
+            let path = self.path.clone();
+            visitor.visit_some(self).map_err(|e: Error| e.or_at(&path))
+        }
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        self.expect(ValueType::Null)?;
+        visitor.visit_unit().map_err(|e: Error| e.or_at(&self.path))
+    }
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_unit(visitor)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        let path = self.path.clone();
+        visitor
+            .visit_newtype_struct(self)
+            .map_err(|e: Error| e.or_at(&path))
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        let access = self.seq_access()?;
+        visitor
+            .visit_seq(access)
+            .map_err(|e: Error| e.or_at(&self.path))
+    }
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(mut self, visitor: V) -> Result<V::Value, Error> {
+        self.expect(ValueType::AttrSet)?;
+        // Values are forced when they are deserialized, so that errors report the attribute name.
+        #[cfg(nix_at_least = "2.33")]
+        let attrs = self.eval_state.attrs_iter_lazy(&self.value);
+        #[cfg(not(nix_at_least = "2.33"))]
+        let attrs = self.eval_state.attrs_iter_strict(&self.value);
+        let access = MapAccess {
+            attrs: attrs.map_err(|e| Error::eval(&self.path, e))?,
+            path: &self.path,
+            current: None,
+        };
+        visitor
+            .visit_map(access)
+            .map_err(|e: Error| e.or_at(&self.path))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        mut self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        self.expect(ValueType::AttrSet)?;
+        let access = StructAccess {
+            eval_state: self.eval_state,
+            attrs: self.value.clone(),
+            path: &self.path,
+            fields: fields.iter(),
+            current: None,
+        };
+        visitor
+            .visit_map(access)
+            .map_err(|e: Error| e.or_at(&self.path))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        mut self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        match self.value_type()? {
+            ValueType::String => {
+                let s = self.get_string()?;
+                visitor
+                    .visit_enum(s.into_deserializer())
+                    .map_err(|e: Error| e.or_at(&self.path))
+            }
+            ValueType::AttrSet => {
+                let names = self
+                    .eval_state
+                    .require_attrs_names_unsorted(&self.value)
+                    .map_err(|e| self.eval_err(e))?;
+                let [variant] = <[String; 1]>::try_from(names).map_err(|names| {
+                    Error::at(
+                        &self.path,
+                        format_args!(
+                            "expected an attrset with a single attribute, got {} attributes",
+                            names.len()
+                        ),
+                    )
+                })?;
+                let value = self
+                    .eval_state
+                    .require_attrs_select(&self.value, &variant)
+                    .map_err(|e| Error::eval(&self.path.attr(&variant), e))?;
+                let path = self.path.attr(&variant);
+                let access = EnumAccess {
+                    variant,
+                    de: Deserializer {
+                        eval_state: self.eval_state,
+                        value,
+                        path,
+                    },
+                };
+                visitor
+                    .visit_enum(access)
+                    .map_err(|e: Error| e.or_at(&self.path))
+            }
+            t => Err(self.invalid_type(&t, "string or attrset")),
+        }
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        self.deserialize_string(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        // Don't force the value
+        visitor.visit_unit()
+    }
+}
+
+struct SeqAccess<'a> {
+    eval_state: &'a mut EvalState,
+    list: Value,
+    path: &'a AttrPath,
+    next: u32,
+    len: u32,
+}
+impl<'de> de::SeqAccess<'de> for SeqAccess<'_> {
+    type Error = Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, Error> {
+        if self.next >= self.len {
+            return Ok(None);
+        }
+        let i = self.next;
+        self.next += 1;
+        let path = self.path.index(i);
+        let value = self
+            .eval_state
+            .require_list_select_idx_strict(&self.list, i)
+            .map_err(|e| Error::eval(&path, e))?
+            .ok_or_else(|| Error::at(&path, "list element is missing"))?;
+        seed.deserialize(Deserializer {
+            eval_state: self.eval_state,
+            value,
+            path,
+        })
+        .map(Some)
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some((self.len - self.next) as usize)
+    }
+}
+
+struct MapAccess<'a> {
+    attrs: AttrsIter<'a>,
+    path: &'a AttrPath,
+    current: Option<(String, Value)>,
+}
+impl<'de> de::MapAccess<'de> for MapAccess<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        match self.attrs.next() {
+            None => Ok(None),
+            Some(attr) => {
+                let (name, value) = attr.map_err(|e| Error::eval(self.path, e))?;
+                let key = seed
+                    .deserialize(name.as_str().into_deserializer())
+                    .map_err(|e: Error| e.or_at(&self.path.attr(&name)))?;
+                self.current = Some((name, value));
+                Ok(Some(key))
+            }
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (name, value) = self
+            .current
+            .take()
+            .ok_or_else(|| Error::at(self.path, "next_value called before next_key"))?;
+        seed.deserialize(Deserializer {
+            eval_state: self.attrs.eval_state(),
+            value,
+            path: self.path.attr(&name),
+        })
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.attrs.len())
+    }
+}
+
+/// Like [`MapAccess`], but only visits the attributes that are fields of the struct.
+struct StructAccess<'a> {
+    eval_state: &'a mut EvalState,
+    attrs: Value,
+    path: &'a AttrPath,
+    fields: std::slice::Iter<'static, &'static str>,
+    current: Option<(&'static str, Value)>,
+}
+impl<'de> de::MapAccess<'de> for StructAccess<'_> {
+    type Error = Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, Error> {
+        for &field in self.fields.by_ref() {
+            let value = self
+                .eval_state
+                .require_attrs_select_opt(&self.attrs, field)
+                .map_err(|e| Error::eval(&self.path.attr(field), e))?;
+            if let Some(value) = value {
+                let key = seed.deserialize(field.into_deserializer())?;
+                self.current = Some((field, value));
+                return Ok(Some(key));
+            }
+        }
+        Ok(None)
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, Error> {
+        let (field, value) = self
+            .current
+            .take()
+            .ok_or_else(|| Error::at(self.path, "next_value called before next_key"))?;
+        seed.deserialize(Deserializer {
+            eval_state: self.eval_state,
+            value,
+            path: self.path.attr(field),
+        })
+    }
+}
+
+struct EnumAccess<'a> {
+    variant: String,
+    de: Deserializer<'a>,
+}
+impl<'de, 'a> de::EnumAccess<'de> for EnumAccess<'a> {
+    type Error = Error;
+    type Variant = Deserializer<'a>;
+
+    fn variant_seed<V: DeserializeSeed<'de>>(
+        self,
+        seed: V,
+    ) -> Result<(V::Value, Self::Variant), Error> {
+        let variant = seed
+            .deserialize(self.variant.into_deserializer())
+            .map_err(|e: Error| e.or_at(&self.de.path))?;
+        Ok((variant, self.de))
+    }
+}
+impl<'de> de::VariantAccess<'de> for Deserializer<'_> {
+    type Error = Error;
+
+    fn unit_variant(self) -> Result<(), Error> {
+        // { variant = <anything>; } is accepted, like serde_json's { "variant": null }, but not forced
+        Ok(())
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, Error> {
+        seed.deserialize(self)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_tuple(self, len, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Error> {
+        de::Deserializer::deserialize_struct(self, "", fields, visitor)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval_state::gc_register_my_thread;
+    use nix_bindings_store::store::Store;
+    use nix_bindings_util::context::NixError;
+    use serde::Deserialize;
+    use std::collections::{BTreeMap, HashMap};
+
+    fn eval<T: DeserializeOwned>(expr: &str) -> Result<T, Error> {
+        let guard = gc_register_my_thread().unwrap();
+        let store = Store::open(None, HashMap::new()).unwrap();
+        let mut es = EvalState::new(store, []).unwrap();
+        let v = es.eval_from_string(expr, "<test>").unwrap();
+        let r = from_value(&mut es, &v);
+        drop(guard);
+        r
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Nginx {
+        enable: bool,
+        port: u16,
+        root: Option<String>,
+        #[serde(rename = "extraConfig", default)]
+        extra_config: String,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Services {
+        nginx: Nginx,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Config {
+        services: Services,
+    }
+
+    #[test]
+    fn de_struct() {
+        let c: Config = eval(
+            r#"{
+                services.nginx = { enable = true; port = 80; root = null; unused = throw "not forced"; };
+                other = throw "not forced";
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(
+            c.services.nginx,
+            Nginx {
+                enable: true,
+                port: 80,
+                root: None,
+                extra_config: String::new(),
+            }
+        );
+    }
+
+    #[test]
+    fn de_struct_lazy_fields() {
+        let n: Nginx = eval(
+            r#"let port = 40 * 2; in { enable = !false; inherit port; root = "/var/" + "www"; extraConfig = ""; }"#,
+        )
+        .unwrap();
+        assert_eq!(n.port, 80);
+        assert_eq!(n.root.as_deref(), Some("/var/www"));
+    }
+
+    #[test]
+    fn de_error_path() {
+        let e =
+            eval::<Config>(r#"{ services.nginx = { enable = true; port = "80"; }; }"#).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "services.nginx.port: expected int, got string"
+        );
+        assert_eq!(e.path(), Some("services.nginx.port"));
+        assert_eq!(e.msg(), "expected int, got string");
+    }
+
+    #[test]
+    fn de_error_out_of_range() {
+        let e = eval::<Nginx>(r#"{ enable = true; port = 100000; }"#).unwrap_err();
+        assert_eq!(e.path(), Some("port"));
+        assert!(e.msg().contains("100000"), "{}", e);
+    }
+
+    #[test]
+    fn de_error_missing_field() {
+        let e = eval::<Config>(r#"{ services.nginx = { port = 80; }; }"#).unwrap_err();
+        assert_eq!(e.to_string(), "services.nginx: missing field `enable`");
+    }
+
+    #[test]
+    fn de_error_eval() {
+        let e = eval::<Vec<i64>>(r#"[ 1 (throw "oh no") ]"#).unwrap_err();
+        assert_eq!(e.path(), Some("[1]"));
+        assert!(e.msg().contains("oh no"));
+        let source = std::error::Error::source(&e).unwrap();
+        assert!(source.downcast_ref::<NixError>().is_some());
+    }
+
+    #[test]
+    fn de_error_path_quoted() {
+        let e = eval::<HashMap<String, HashMap<String, bool>>>(r#"{ "a b".c = 1; }"#).unwrap_err();
+        assert_eq!(e.to_string(), "\"a b\".c: expected bool, got int");
+    }
+
+    #[test]
+    fn de_option() {
+        let v: Vec<Option<i64>> = eval("[ null 1 ]").unwrap();
+        assert_eq!(v, [None, Some(1)]);
+    }
+
+    #[test]
+    fn de_seq() {
+        let v: Vec<String> = eval(r#"[ "a" ("b" + "c") ]"#).unwrap();
+        assert_eq!(v, ["a", "bc"]);
+        let v: (i64, bool, f64) = eval("[ 1 true 1.5 ]").unwrap();
+        assert_eq!(v, (1, true, 1.5));
+    }
+
+    #[test]
+    fn de_map() {
+        let v: BTreeMap<String, i64> = eval("{ b = 2; a = 1; }").unwrap();
+        assert_eq!(v, BTreeMap::from([("a".into(), 1), ("b".into(), 2)]));
+    }
+
+    #[test]
+    fn de_error_map_value() {
+        let e = eval::<BTreeMap<String, i64>>(r#"{ a = 1; b = throw "oh no"; }"#).unwrap_err();
+        assert!(e.msg().contains("oh no"), "{}", e);
+        #[cfg(nix_at_least = "2.33")]
+        assert_eq!(e.path(), Some("b"));
+    }
+
+    #[test]
+    fn de_float_from_int() {
+        let v: f64 = eval("3").unwrap();
+        assert_eq!(v, 3.0);
+    }
+
+    #[test]
+    fn de_path() {
+        let v: std::path::PathBuf = eval("/foo/bar").unwrap();
+        assert_eq!(v, std::path::Path::new("/foo/bar"));
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Protocol {
+        Http,
+        Https { cert: String },
+        Port(u16),
+    }
+
+    #[test]
+    fn de_enum() {
+        let v: Vec<Protocol> =
+            eval(r#"[ "Http" { Https.cert = "x.pem"; } { Port = 8080; } ]"#).unwrap();
+        assert_eq!(
+            v,
+            [
+                Protocol::Http,
+                Protocol::Https {
+                    cert: "x.pem".into()
+                },
+                Protocol::Port(8080)
+            ]
+        );
+    }
+
+    #[test]
+    fn de_enum_invalid() {
+        let e = eval::<Protocol>(r#"{ Http = null; Port = 1; }"#).unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "expected an attrset with a single attribute, got 2 attributes"
+        );
+        let e = eval::<Protocol>(r#""Ftp""#).unwrap_err();
+        assert!(e.msg().contains("unknown variant `Ftp`"), "{}", e);
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    #[serde(untagged)]
+    enum Untagged {
+        Int(i64),
+        Str(String),
+        List(Vec<Untagged>),
+    }
+
+    #[test]
+    fn de_any() {
+        let v: Untagged = eval(r#"[ 1 "a" [ ] ]"#).unwrap();
+        assert_eq!(
+            v,
+            Untagged::List(vec![
+                Untagged::Int(1),
+                Untagged::Str("a".into()),
+                Untagged::List(vec![])
+            ])
+        );
+    }
+
+    #[test]
+    fn de_function() {
+        let e = eval::<Untagged>("x: x").unwrap_err();
+        assert_eq!(e.to_string(), "cannot deserialize a function");
+    }
+}
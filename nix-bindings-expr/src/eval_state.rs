@@ -65,6 +65,7 @@
 //! For example, a list in WHNF has its length determined but individual elements may remain unevaluated thunks.
 //! Methods marked as "strict" in this API force WHNF evaluation of their results, but do not perform deep evaluation
 //! of arbitrarily nested structures unless explicitly documented otherwise.
+//! Use [`force_deep`](EvalState::force_deep) for deep evaluation.
 //!
 //! ### Thread Safety and Memory Management
 //!
@@ -138,32 +139,29 @@ use nix_bindings_expr_sys as raw;
 use nix_bindings_store::path::StorePath;
 use nix_bindings_store::store::{Store, StoreWeak};
 use nix_bindings_store_sys as raw_store;
-use nix_bindings_util::context::Context;
+use nix_bindings_util::context::{Context, NixError, NixErrorKind};
 use nix_bindings_util::string_return::{
     callback_get_result_string, callback_get_result_string_data,
 };
 use nix_bindings_util::{check_call, check_call_opt_key, result_string_init};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::ffi::{c_char, CString};
 use std::iter::FromIterator;
 use std::os::raw::c_uint;
 use std::ptr::{null, null_mut, NonNull};
-use std::sync::{Arc, LazyLock, Weak};
+use std::sync::{Arc, LazyLock, Mutex, Weak};
 
-static INIT: LazyLock<Result<()>> = LazyLock::new(|| unsafe {
+static INIT: LazyLock<Result<(), NixError>> = LazyLock::new(|| unsafe {
     gc::GC_allow_register_threads();
-    check_call!(raw::libexpr_init(&mut Context::new()))?;
-    Ok(())
+    nix_bindings_util::thread::set_thread_setup(|| Ok(Box::new(gc_register_my_thread()?)));
+    let mut ctx = Context::new();
+    raw::libexpr_init(ctx.ptr());
+    ctx.get_err().map_or(Ok(()), Err)
 });
 
 pub fn init() -> Result<()> {
-    let x = INIT.as_ref();
-    match x {
-        Ok(_) => Ok(()),
-        Err(e) => {
-            // Couldn't just clone the error, so we have to print it here.
-            Err(anyhow::format_err!("nix_bindings_expr::init error: {}", e))
-        }
-    }
+    INIT.clone().context("nix_bindings_expr::init error")
 }
 
 /// A string value with its associated [store paths](https://nix.dev/manual/nix/stable/store/store-path.html).
@@ -198,6 +196,8 @@ impl EvalStateWeak {
 
 struct EvalStateRef {
     eval_state: NonNull<raw::EvalState>,
+    /// Functions written in Nix that the bindings use internally, see [`EvalState::eval_helper`].
+    helpers: Mutex<HashMap<&'static str, Value>>,
 }
 impl EvalStateRef {
     /// Returns a raw pointer to the underlying EvalState.
@@ -243,6 +243,25 @@ impl Drop for EvalStateRef {
 /// # Ok(())
 /// # }
 /// ```
+///
+/// # Settings
+///
+/// The C API can not set evaluator settings for a single [`EvalState`], such as
+/// [`pure-eval`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-pure-eval),
+/// [`restrict-eval`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-restrict-eval),
+/// [`allowed-uris`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-allowed-uris) or
+/// [`allow-import-from-derivation`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-allow-import-from-derivation).
+/// They are read from `nix.conf` and the `NIX_CONFIG` environment variable when [ambient settings][EvalStateBuilder::load_ambient_settings] are loaded,
+/// so they apply to every [`EvalState`] in the process.
+/// Set `NIX_CONFIG` before the process starts, or before it starts any threads; changing the environment while other threads may read it is undefined behavior.
+///
+/// ## Pure and restricted evaluation
+///
+/// For the reason above, there are no builder methods such as `pure_eval`, `restrict_eval`, `allowed_paths`, `allowed_uris` or `allow_import_from_derivation`.
+/// Set the corresponding settings in `nix.conf` or `NIX_CONFIG` for the whole process instead.
+///
+/// In restricted evaluation, the [lookup path][EvalStateBuilder::lookup_path] entries may be accessed. Pure evaluation does not use the lookup path at all.
+/// Accesses to forbidden paths and URIs fail with a [`NixErrorKind::Restricted`] error.
 #[cfg(nix_at_least = "2.26")]
 pub struct EvalStateBuilder {
     eval_state_builder: *mut raw::eval_state_builder,
@@ -288,8 +307,9 @@ impl EvalStateBuilder {
     /// Sets whether to load settings from the ambient environment.
     ///
     /// When enabled (default), calls `nix_eval_state_builder_load` to load settings
-    /// from NIX_CONFIG and other environment variables. When disabled, only the
-    /// explicitly configured settings are used.
+    /// from NIX_CONFIG and other environment variables. When disabled, Nix's defaults are used.
+    ///
+    /// See [Settings](EvalStateBuilder#settings) for why settings can not be set for a single [`EvalState`].
     pub fn load_ambient_settings(mut self, load: bool) -> Self {
         self.load_ambient_settings = load;
         self
@@ -335,6 +355,7 @@ impl EvalStateBuilder {
                 eval_state: NonNull::new(eval_state).unwrap_or_else(|| {
                     panic!("nix_state_create returned a null pointer without an error")
                 }),
+                helpers: Mutex::new(HashMap::new()),
             }),
             store: self.store.clone(),
             context,
@@ -432,6 +453,18 @@ impl EvalState {
         }
     }
 
+    /// Evaluates `expr`, which the bindings use internally, once per evaluator.
+    ///
+    /// The result is cached under `name`, and shared with clones of this `EvalState`.
+    pub(crate) fn eval_helper(&mut self, name: &'static str, expr: &str) -> Result<Value> {
+        if let Some(v) = self.eval_state.helpers.lock().unwrap().get(name) {
+            return Ok(v.clone());
+        }
+        let v = self.eval_from_string(expr, &format!("<nix-bindings-expr {name}>"))?;
+        let mut helpers = self.eval_state.helpers.lock().unwrap();
+        Ok(helpers.entry(name).or_insert(v).clone())
+    }
+
     /// Forces [evaluation](https://nix.dev/manual/nix/latest/language/evaluation.html) of a value to [weak head normal form](https://nix.dev/manual/nix/latest/language/evaluation.html?highlight=WHNF#values).
     ///
     /// Converts [thunks](https://nix.dev/manual/nix/latest/language/evaluation.html#laziness) to their evaluated form. Does not modify already-evaluated values.
@@ -452,6 +485,75 @@ impl EvalState {
         Ok(())
     }
 
+    /// Forces [evaluation](https://nix.dev/manual/nix/latest/language/evaluation.html) of a value and everything it contains, like [`builtins.deepSeq`](https://nix.dev/manual/nix/latest/language/builtins.html#builtins-deepSeq).
+    ///
+    /// Lists and attribute sets are traversed recursively. Functions are not called.
+    /// Values that have already been visited are skipped, so cyclic values such as `let x = { inherit x; }; in x` are supported.
+    ///
+    /// If `max_depth` is given, only values up to that nesting depth are forced: `Some(0)` is equivalent to [`force`][`EvalState::force`],
+    /// `Some(1)` also forces the elements of a list or attribute set, and so on. Deeper values are left as they are.
+    ///
+    /// See also: [Shared Evaluation State](Value#shared-evaluation-state)
+    #[doc(alias = "deep_seq")]
+    #[doc(alias = "deepseq")]
+    #[doc(alias = "nix_value_force_deep")]
+    #[doc(alias = "value_force_deep")]
+    pub fn force_deep(&mut self, v: &Value, max_depth: Option<usize>) -> Result<()> {
+        match max_depth {
+            // Nix's implementation also keeps track of visited values
+            None => {
+                unsafe {
+                    check_call!(raw::value_force_deep(
+                        &mut self.context,
+                        self.eval_state.as_ptr(),
+                        v.raw_ptr()
+                    ))
+                }?;
+                Ok(())
+            }
+            Some(max_depth) => self.force_deep_rec(v, max_depth, &mut HashMap::new()),
+        }
+    }
+
+    fn force_deep_rec(
+        &mut self,
+        v: &Value,
+        depth: usize,
+        // The largest remaining depth that each value was visited with
+        seen: &mut HashMap<*mut raw::Value, usize>,
+    ) -> Result<()> {
+        let t = self.value_type(v)?;
+        if depth == 0 {
+            return Ok(());
+        }
+        match seen.entry(unsafe { v.raw_ptr() }) {
+            // A shared value that was reached before with at least as much depth left
+            Entry::Occupied(entry) if *entry.get() >= depth => return Ok(()),
+            Entry::Occupied(mut entry) => {
+                entry.insert(depth);
+            }
+            Entry::Vacant(entry) => {
+                entry.insert(depth);
+            }
+        }
+        match t {
+            ValueType::AttrSet => {
+                let attrs = self.attrs_iter_strict(v)?.collect::<Result<Vec<_>>>()?;
+                for (_name, value) in attrs {
+                    self.force_deep_rec(&value, depth - 1, seen)?;
+                }
+            }
+            ValueType::List => {
+                let items: Vec<Value> = self.require_list_strict(v)?;
+                for value in items {
+                    self.force_deep_rec(&value, depth - 1, seen)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
     /// Returns the type of a value without forcing [evaluation](https://nix.dev/manual/nix/latest/language/evaluation.html).
     ///
     /// Returns [`None`] if the value is an unevaluated [thunk](https://nix.dev/manual/nix/latest/language/evaluation.html#laziness).
@@ -532,6 +634,24 @@ impl EvalState {
         unsafe { check_call!(raw::get_int(&mut self.context, v.raw_ptr())) }
     }
 
+    /// Extracts the value from a [float][`ValueType::Float`] Nix value.
+    ///
+    /// Forces [evaluation](https://nix.dev/manual/nix/latest/language/evaluation.html) and verifies the value is a float.
+    ///
+    /// Returns the float value if successful, or an [`Err`] if evaluation failed or the value is not a float.
+    /// Integers are not converted; use [`require_int`][`EvalState::require_int`] for those.
+    #[doc(alias = "double")]
+    #[doc(alias = "number")]
+    #[doc(alias = "nix_get_float")]
+    #[doc(alias = "get_float")]
+    pub fn require_float(&mut self, v: &Value) -> Result<f64> {
+        let t = self.value_type(v)?;
+        if t != ValueType::Float {
+            bail!("expected a float, but got a {:?}", t);
+        }
+        unsafe { check_call!(raw::get_float(&mut self.context, v.raw_ptr())) }
+    }
+
     /// Extracts the value from a [boolean][`ValueType::Bool`] Nix value.
     ///
     /// Forces [evaluation](https://nix.dev/manual/nix/latest/language/evaluation.html) and verifies the value is a boolean.
@@ -643,6 +763,59 @@ impl EvalState {
         Ok(attrs)
     }
 
+    /// Iterates over the attributes of an [attribute set][`ValueType::AttrSet`] Nix value, forcing each attribute value.
+    ///
+    /// Forces [evaluation](https://nix.dev/manual/nix/latest/language/evaluation.html) and verifies the value is an attribute set.
+    ///
+    /// Yields `(name, value)` pairs in Nix's internal order, which is not sorted by name.
+    /// Each value is forced to [WHNF](https://nix.dev/manual/nix/latest/language/evaluation.html#values) when it is yielded,
+    /// but its children are not. See [`attrs_iter_lazy`][`EvalState::attrs_iter_lazy`] for a variant that does not force the values.
+    ///
+    /// This is faster than [`require_attrs_names_unsorted`][`EvalState::require_attrs_names_unsorted`]
+    /// followed by [`require_attrs_select`][`EvalState::require_attrs_select`], because each attribute is retrieved by index.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use nix_bindings_expr::eval_state::{EvalState, test_init, gc_register_my_thread};
+    /// # use nix_bindings_store::store::Store;
+    /// # use std::collections::HashMap;
+    /// # fn example() -> anyhow::Result<()> {
+    /// # test_init();
+    /// # let guard = gc_register_my_thread()?;
+    /// let store = Store::open(None, HashMap::new())?;
+    /// let mut es = EvalState::new(store, [])?;
+    ///
+    /// let attrs = es.eval_from_string("{ a = 1; b = 2; }", "<example>")?;
+    /// let mut sum = 0;
+    /// let mut iter = es.attrs_iter_strict(&attrs)?;
+    /// while let Some(attr) = iter.next() {
+    ///     let (_name, value) = attr?;
+    ///     sum += iter.eval_state().require_int(&value)?;
+    /// }
+    /// assert_eq!(sum, 3);
+    /// # drop(guard);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[doc(alias = "items")]
+    #[doc(alias = "entries")]
+    #[doc(alias = "nix_get_attr_byidx")]
+    #[doc(alias = "get_attr_byidx")]
+    pub fn attrs_iter_strict(&mut self, v: &Value) -> Result<AttrsIter<'_>> {
+        AttrsIter::new(self, v, false)
+    }
+
+    /// Like [`attrs_iter_strict`][`EvalState::attrs_iter_strict`], but does not force the attribute values.
+    ///
+    /// The attribute set itself is still forced. Yielded values may be thunks.
+    #[cfg(nix_at_least = "2.33")]
+    #[doc(alias = "nix_get_attr_byidx_lazy")]
+    #[doc(alias = "get_attr_byidx_lazy")]
+    pub fn attrs_iter_lazy(&mut self, v: &Value) -> Result<AttrsIter<'_>> {
+        AttrsIter::new(self, v, true)
+    }
+
     /// Extracts an attribute value from an [attribute set][`ValueType::AttrSet`] Nix value.
     ///
     /// Forces [evaluation](https://nix.dev/manual/nix/latest/language/evaluation.html) and verifies the value is an attribute set.
@@ -672,8 +845,14 @@ impl EvalState {
                     // is simply missing, so we provide a better one. (Note that
                     // missing attributes requested by Nix expressions OTOH is a
                     // different error message which works fine.)
-                    if e.to_string() == "missing attribute" {
-                        bail!("attribute `{}` not found", attr_name.to_string_lossy());
+                    let is_missing = e
+                        .downcast_ref::<NixError>()
+                        .is_some_and(|e| e.kind() == NixErrorKind::Key);
+                    if is_missing {
+                        Err(e.context(format!(
+                            "attribute `{}` not found",
+                            attr_name.to_string_lossy()
+                        )))
                     } else {
                         Err(e)
                     }
@@ -812,6 +991,72 @@ impl EvalState {
         Ok(v)
     }
 
+    /// Creates a new [float][`ValueType::Float`] Nix value.
+    ///
+    /// The value is stored as is, including NaN, infinities and negative zero.
+    #[doc(alias = "make_float")]
+    #[doc(alias = "create_float")]
+    #[doc(alias = "float_value")]
+    #[doc(alias = "double_value")]
+    pub fn new_value_float(&mut self, f: f64) -> Result<Value> {
+        let v = unsafe {
+            let value = self.new_value_uninitialized()?;
+            check_call!(raw::init_float(&mut self.context, value.raw_ptr(), f))?;
+            value
+        };
+        Ok(v)
+    }
+
+    /// Creates a new [boolean][`ValueType::Bool`] Nix value.
+    #[doc(alias = "make_bool")]
+    #[doc(alias = "create_bool")]
+    #[doc(alias = "bool_value")]
+    #[doc(alias = "boolean_value")]
+    pub fn new_value_bool(&mut self, b: bool) -> Result<Value> {
+        let v = unsafe {
+            let value = self.new_value_uninitialized()?;
+            check_call!(raw::init_bool(&mut self.context, value.raw_ptr(), b))?;
+            value
+        };
+        Ok(v)
+    }
+
+    /// Creates a new [`null`][`ValueType::Null`] Nix value.
+    #[doc(alias = "make_null")]
+    #[doc(alias = "create_null")]
+    #[doc(alias = "null_value")]
+    pub fn new_value_null(&mut self) -> Result<Value> {
+        let v = unsafe {
+            let value = self.new_value_uninitialized()?;
+            check_call!(raw::init_null(&mut self.context, value.raw_ptr()))?;
+            value
+        };
+        Ok(v)
+    }
+
+    /// Creates a new [path][`ValueType::Path`] Nix value.
+    ///
+    /// The path is resolved in the evaluator's root source accessor, like a Nix path literal, so it should be absolute.
+    /// The path is not required to exist.
+    #[doc(alias = "make_path")]
+    #[doc(alias = "create_path")]
+    #[doc(alias = "path_value")]
+    #[doc(alias = "nix_init_path_string")]
+    pub fn new_value_path(&mut self, path: &str) -> Result<Value> {
+        let path = CString::new(path).with_context(|| "new_value_path: contains null byte")?;
+        let v = unsafe {
+            let value = self.new_value_uninitialized()?;
+            check_call!(raw::init_path_string(
+                &mut self.context,
+                self.eval_state.as_ptr(),
+                value.raw_ptr(),
+                path.as_ptr()
+            ))?;
+            value
+        };
+        Ok(v)
+    }
+
     /// Creates a new [thunk](https://nix.dev/manual/nix/latest/language/evaluation.html#laziness) Nix value.
     ///
     /// The [thunk](https://nix.dev/manual/nix/latest/language/evaluation.html#laziness) will lazily evaluate to the result of the given Rust function when forced.
@@ -876,6 +1121,30 @@ impl EvalState {
         }
         self.get_string(value)
     }
+    /// Extracts the path from a [path][`ValueType::Path`] Nix value.
+    ///
+    /// Forces [evaluation](https://nix.dev/manual/nix/latest/language/evaluation.html) and verifies the value is a path.
+    /// Returns the path as a string if successful, or an [`Err`] if evaluation failed or the value is not a path.
+    ///
+    /// The path is not copied to the store, and strings are not coerced to paths.
+    #[doc(alias = "nix_get_path_string")]
+    #[doc(alias = "get_path_string")]
+    pub fn require_path(&mut self, value: &Value) -> Result<String> {
+        let t = self.value_type(value)?;
+        if t != ValueType::Path {
+            bail!("expected a path, but got a {:?}", t);
+        }
+        let cstr_ptr: *const c_char =
+            unsafe { check_call!(raw::get_path_string(&mut self.context, value.raw_ptr())) }?;
+        if cstr_ptr.is_null() {
+            bail!("nix_get_path_string returned a null pointer");
+        }
+        let cstr = unsafe { std::ffi::CStr::from_ptr(cstr_ptr) };
+        let s = cstr
+            .to_str()
+            .map_err(|e| anyhow::format_err!("Nix path is not valid UTF-8: {}", e))?;
+        Ok(s.to_owned())
+    }
     /// Realises a [string][`ValueType::String`] Nix value with context information.
     ///
     /// Forces [evaluation](https://nix.dev/manual/nix/latest/language/evaluation.html), verifies the value is a string, and builds any derivations
@@ -1065,6 +1334,50 @@ impl EvalState {
         Ok(value)
     }
 
+    /// Creates a new [list][`ValueType::List`] Nix value from an iterator of values.
+    ///
+    /// Accepts any iterator that yields [`Value`]s and has an exact size, such as [`Vec`] or an array.
+    /// The elements are not forced.
+    ///
+    /// To build a list incrementally, use [`ListBuilder`].
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use nix_bindings_expr::eval_state::{EvalState, test_init, gc_register_my_thread};
+    /// # use nix_bindings_store::store::Store;
+    /// # use std::collections::HashMap;
+    /// # fn example() -> anyhow::Result<()> {
+    /// # test_init();
+    /// # let guard = gc_register_my_thread()?;
+    /// let store = Store::open(None, HashMap::new())?;
+    /// let mut es = EvalState::new(store, [])?;
+    /// let a = es.new_value_int(1)?;
+    /// let b = es.new_value_str("two")?;
+    ///
+    /// let list = es.new_value_list([a, b])?;
+    /// assert_eq!(es.require_list_size(&list)?, 2);
+    /// # drop(guard);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[doc(alias = "make_list")]
+    #[doc(alias = "create_list")]
+    #[doc(alias = "array")]
+    #[doc(alias = "nix_make_list")]
+    pub fn new_value_list<I>(&mut self, items: I) -> Result<Value>
+    where
+        I: IntoIterator<Item = Value>,
+        I::IntoIter: ExactSizeIterator,
+    {
+        let iter = items.into_iter();
+        let mut list_builder = ListBuilder::new(self, iter.len())?;
+        for value in iter {
+            list_builder.push(&value)?;
+        }
+        list_builder.finish()
+    }
+
     /// Creates a new [attribute set][`ValueType::AttrSet`] Nix value from an iterator of name-value pairs.
     ///
     /// Accepts any iterator that yields `(String, Value)` pairs and has an exact size.
@@ -1112,7 +1425,7 @@ impl EvalState {
     {
         let iter = attrs.into_iter();
         let size = iter.len();
-        let bindings_builder = BindingsBuilder::new(self, size)?;
+        let bindings_builder = BindingsBuilderRef::new(&mut self.context, &self.eval_state, size)?;
         for (name, value) in iter {
             let name =
                 CString::new(name).with_context(|| "new_value_attrs: name contains null byte")?;
@@ -1137,94 +1450,474 @@ impl EvalState {
     }
 }
 
-// Internal RAII helper; could be refactored and made pub
-struct BindingsBuilder {
-    ptr: *mut raw::BindingsBuilder,
+/// Iterator over the attributes of an attribute set, created by [`EvalState::attrs_iter_strict`] or [`EvalState::attrs_iter_lazy`].
+///
+/// Holds on to the [`EvalState`]; use [`eval_state`][`AttrsIter::eval_state`] to work with the yielded values during iteration.
+pub struct AttrsIter<'a> {
+    eval_state: &'a mut EvalState,
+    attrs: Value,
+    next: c_uint,
+    len: c_uint,
+    #[cfg_attr(not(nix_at_least = "2.33"), allow(dead_code))]
+    lazy: bool,
 }
-impl Drop for BindingsBuilder {
-    fn drop(&mut self) {
-        unsafe {
-            raw::bindings_builder_free(self.ptr);
+impl<'a> AttrsIter<'a> {
+    fn new(eval_state: &'a mut EvalState, v: &Value, lazy: bool) -> Result<Self> {
+        let t = eval_state.value_type(v)?;
+        if t != ValueType::AttrSet {
+            bail!("expected an attrset, but got a {:?}", t);
         }
+        let len =
+            unsafe { check_call!(raw::get_attrs_size(&mut eval_state.context, v.raw_ptr())) }?;
+        Ok(AttrsIter {
+            eval_state,
+            attrs: v.clone(),
+            next: 0,
+            len,
+            lazy,
+        })
     }
-}
-impl BindingsBuilder {
-    fn new(eval_state: &mut EvalState, capacity: usize) -> Result<Self> {
-        let ptr = unsafe {
-            check_call!(raw::make_bindings_builder(
-                &mut eval_state.context,
-                eval_state.eval_state.as_ptr(),
-                capacity
+
+    /// The [`EvalState`] that the attribute set belongs to.
+    pub fn eval_state(&mut self) -> &mut EvalState {
+        self.eval_state
+    }
+
+    fn get(&mut self, i: c_uint) -> Result<(String, Value)> {
+        let es = &mut *self.eval_state;
+        let mut name: *const c_char = null();
+        #[cfg(nix_at_least = "2.33")]
+        let get_attr_byidx = if self.lazy {
+            raw::get_attr_byidx_lazy
+        } else {
+            raw::get_attr_byidx
+        };
+        #[cfg(not(nix_at_least = "2.33"))]
+        let get_attr_byidx = raw::get_attr_byidx;
+        let value = unsafe {
+            check_call!(get_attr_byidx(
+                &mut es.context,
+                self.attrs.raw_ptr(),
+                es.eval_state.as_ptr(),
+                i,
+                &mut name
             ))
         }?;
-        Ok(BindingsBuilder { ptr })
+        let value = unsafe { Value::new(value) };
+        if name.is_null() {
+            bail!("nix_get_attr_byidx returned a null name");
+        }
+        let name = unsafe { std::ffi::CStr::from_ptr(name) }
+            .to_str()
+            .map_err(|e| anyhow::format_err!("Nix attrset key is not valid UTF-8: {}", e))?;
+        Ok((name.to_owned(), value))
     }
 }
+impl Iterator for AttrsIter<'_> {
+    type Item = Result<(String, Value)>;
 
-/// Triggers garbage collection immediately.
-#[doc(alias = "garbage_collect")]
-#[doc(alias = "collect")]
-#[doc(alias = "gc")]
-pub fn gc_now() {
-    unsafe {
-        raw::gc_now();
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.len {
+            return None;
+        }
+        let i = self.next;
+        self.next += 1;
+        Some(self.get(i))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let n = (self.len - self.next) as usize;
+        (n, Some(n))
     }
 }
+impl ExactSizeIterator for AttrsIter<'_> {}
 
-/// RAII guard for thread registration with the garbage collector.
+/// Builder for a [list][`ValueType::List`] Nix value of a known size.
 ///
-/// Automatically unregisters the thread when dropped.
-pub struct ThreadRegistrationGuard {
-    must_unregister: bool,
+/// Create one with [`ListBuilder::new`], [`push`][`ListBuilder::push`] exactly `capacity` values,
+/// and turn it into a [`Value`] with [`finish`][`ListBuilder::finish`].
+/// The builder does not borrow the [`EvalState`], so new values can be created while the list is being built.
+///
+/// For lists whose elements are already available, [`EvalState::new_value_list`] is simpler.
+///
+/// # Examples
+///
+/// ```rust
+/// # use nix_bindings_expr::eval_state::{EvalState, ListBuilder, test_init, gc_register_my_thread};
+/// # use nix_bindings_store::store::Store;
+/// # use std::collections::HashMap;
+/// # fn example() -> anyhow::Result<()> {
+/// # test_init();
+/// # let guard = gc_register_my_thread()?;
+/// let store = Store::open(None, HashMap::new())?;
+/// let mut es = EvalState::new(store, [])?;
+///
+/// let mut builder = ListBuilder::new(&mut es, 3)?;
+/// for i in 0..3 {
+///     let v = es.new_value_int(i * 10)?;
+///     builder.push(&v)?;
+/// }
+/// let list = builder.finish()?;
+/// assert_eq!(es.require_list_size(&list)?, 3);
+/// # drop(guard);
+/// # Ok(())
+/// # }
+/// ```
+#[doc(alias = "nix_make_list_builder")]
+#[doc(alias = "list_builder")]
+pub struct ListBuilder {
+    ptr: *mut raw::ListBuilder,
+    capacity: u32,
+    len: u32,
+    // Keeps the EvalState alive, because the builder allocates in it.
+    eval_state: Arc<EvalStateRef>,
+    context: Context,
 }
-impl Drop for ThreadRegistrationGuard {
+impl Drop for ListBuilder {
     fn drop(&mut self) {
-        if self.must_unregister {
-            unsafe {
-                gc::GC_unregister_my_thread();
-            }
+        unsafe {
+            raw::list_builder_free(self.ptr);
         }
     }
 }
+impl ListBuilder {
+    /// Creates a builder for a list of exactly `capacity` elements.
+    pub fn new(eval_state: &mut EvalState, capacity: usize) -> Result<Self> {
+        let capacity_u32: u32 = capacity
+            .try_into()
+            .map_err(|_| anyhow::format_err!("ListBuilder: capacity {} is too large", capacity))?;
+        let ptr = unsafe {
+            check_call!(raw::make_list_builder(
+                &mut eval_state.context,
+                eval_state.eval_state.as_ptr(),
+                capacity
+            ))
+        }?;
+        Ok(ListBuilder {
+            ptr,
+            capacity: capacity_u32,
+            len: 0,
+            eval_state: eval_state.eval_state.clone(),
+            context: Context::new(),
+        })
+    }
 
-fn gc_register_my_thread_do_it() -> Result<()> {
-    unsafe {
-        let mut sb: gc::GC_stack_base = gc::GC_stack_base {
-            mem_base: null_mut(),
-        };
-        let r = gc::GC_get_stack_base(&mut sb);
-        if r as u32 != gc::GC_SUCCESS {
-            Err(anyhow::format_err!("GC_get_stack_base failed: {}", r))?;
+    /// The number of elements the finished list will have.
+    pub fn capacity(&self) -> usize {
+        self.capacity as usize
+    }
+
+    /// The number of elements pushed so far.
+    pub fn len(&self) -> usize {
+        self.len as usize
+    }
+
+    /// Returns `true` if no elements have been pushed yet.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Appends a value to the list.
+    ///
+    /// The value is not forced. Returns an [`Err`] if the list is already full.
+    #[doc(alias = "insert")]
+    #[doc(alias = "nix_list_builder_insert")]
+    pub fn push(&mut self, value: &Value) -> Result<()> {
+        if self.len >= self.capacity {
+            bail!(
+                "ListBuilder: cannot push more than {} elements",
+                self.capacity
+            );
         }
-        gc::GC_register_my_thread(&sb);
+        unsafe {
+            check_call!(raw::list_builder_insert(
+                &mut self.context,
+                self.ptr,
+                self.len as c_uint,
+                value.raw_ptr()
+            ))
+        }?;
+        self.len += 1;
         Ok(())
     }
-}
 
-#[doc(alias = "register_thread")]
-#[doc(alias = "thread_setup")]
-#[doc(alias = "gc_register")]
-pub fn gc_register_my_thread() -> Result<ThreadRegistrationGuard> {
-    init()?;
-    unsafe {
-        let already_done = gc::GC_thread_is_registered();
-        if already_done != 0 {
-            return Ok(ThreadRegistrationGuard {
-                must_unregister: false,
-            });
+    /// Creates the list value.
+    ///
+    /// Returns an [`Err`] if fewer than [`capacity`][`ListBuilder::capacity`] elements were pushed.
+    #[doc(alias = "build")]
+    #[doc(alias = "nix_make_list")]
+    pub fn finish(mut self) -> Result<Value> {
+        if self.len != self.capacity {
+            bail!(
+                "ListBuilder: expected {} elements, but got {}",
+                self.capacity,
+                self.len
+            );
         }
-        gc_register_my_thread_do_it()?;
-        Ok(ThreadRegistrationGuard {
-            must_unregister: true,
-        })
+        let value = unsafe {
+            let value = check_call!(raw::alloc_value(
+                &mut self.context,
+                self.eval_state.as_ptr()
+            ))?;
+            Value::new(value)
+        };
+        unsafe { check_call!(raw::make_list(&mut self.context, self.ptr, value.raw_ptr())) }?;
+        Ok(value)
     }
 }
 
-impl Clone for EvalState {
-    fn clone(&self) -> Self {
-        EvalState {
-            eval_state: self.eval_state.clone(),
-            store: self.store.clone(),
+/// What [`BindingsBuilder::insert`] does when an attribute name is inserted twice.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum DuplicateAttrPolicy {
+    /// Return an [`Err`] from [`insert`][`BindingsBuilder::insert`], like a Nix attribute set literal with a repeated name.
+    #[default]
+    Error,
+    /// Replace the previously inserted value, like the `//` operator.
+    Overwrite,
+}
+
+/// Builder for an [attribute set][`ValueType::AttrSet`] Nix value, one attribute at a time.
+///
+/// Unlike [`EvalState::new_value_attrs`], the number of attributes does not need to be known in advance.
+/// The builder grows as needed, and [`with_capacity`][`BindingsBuilder::with_capacity`] or
+/// [`reserve`][`BindingsBuilder::reserve`] can be used to avoid reallocations.
+///
+/// Attributes are collected on the Rust side and handed to Nix in [`finish`][`BindingsBuilder::finish`],
+/// because Nix's own bindings builder has a fixed capacity and does not detect duplicate names.
+///
+/// The builder does not borrow the [`EvalState`], so new values can be created while the attribute set is being built.
+///
+/// # Examples
+///
+/// ```rust
+/// # use nix_bindings_expr::eval_state::{BindingsBuilder, DuplicateAttrPolicy, EvalState, test_init, gc_register_my_thread};
+/// # use nix_bindings_store::store::Store;
+/// # use std::collections::HashMap;
+/// # fn example() -> anyhow::Result<()> {
+/// # test_init();
+/// # let guard = gc_register_my_thread()?;
+/// let store = Store::open(None, HashMap::new())?;
+/// let mut es = EvalState::new(store, [])?;
+///
+/// let mut builder = BindingsBuilder::new(&es).on_duplicate(DuplicateAttrPolicy::Overwrite);
+/// for (name, n) in [("a", 1), ("b", 2), ("a", 3)] {
+///     let v = es.new_value_int(n)?;
+///     builder.insert(name, v)?;
+/// }
+/// let attrs = builder.finish()?;
+///
+/// assert_eq!(es.require_attrs_names(&attrs)?, ["a", "b"]);
+/// let a = es.require_attrs_select(&attrs, "a")?;
+/// assert_eq!(es.require_int(&a)?, 3);
+/// # drop(guard);
+/// # Ok(())
+/// # }
+/// ```
+#[doc(alias = "nix_make_bindings_builder")]
+#[doc(alias = "attrs_builder")]
+pub struct BindingsBuilder {
+    attrs: HashMap<CString, Value>,
+    on_duplicate: DuplicateAttrPolicy,
+    // Keeps the EvalState alive, because finish allocates in it.
+    eval_state: Arc<EvalStateRef>,
+    context: Context,
+}
+impl BindingsBuilder {
+    /// Creates an empty builder.
+    pub fn new(eval_state: &EvalState) -> Self {
+        Self::with_capacity(eval_state, 0)
+    }
+
+    /// Creates an empty builder with room for at least `capacity` attributes.
+    pub fn with_capacity(eval_state: &EvalState, capacity: usize) -> Self {
+        BindingsBuilder {
+            attrs: HashMap::with_capacity(capacity),
+            on_duplicate: DuplicateAttrPolicy::default(),
+            eval_state: eval_state.eval_state.clone(),
+            context: Context::new(),
+        }
+    }
+
+    /// Sets what happens when a name is inserted more than once. The default is [`DuplicateAttrPolicy::Error`].
+    pub fn on_duplicate(mut self, policy: DuplicateAttrPolicy) -> Self {
+        self.on_duplicate = policy;
+        self
+    }
+
+    /// Reserves room for at least `additional` more attributes.
+    pub fn reserve(&mut self, additional: usize) {
+        self.attrs.reserve(additional);
+    }
+
+    /// The number of attributes the builder can hold without reallocating.
+    pub fn capacity(&self) -> usize {
+        self.attrs.capacity()
+    }
+
+    /// The number of distinct attributes inserted so far.
+    pub fn len(&self) -> usize {
+        self.attrs.len()
+    }
+
+    /// Returns `true` if no attributes have been inserted yet.
+    pub fn is_empty(&self) -> bool {
+        self.attrs.is_empty()
+    }
+
+    /// Returns `true` if an attribute with this name has been inserted.
+    pub fn contains(&self, name: &str) -> bool {
+        CString::new(name).is_ok_and(|name| self.attrs.contains_key(&name))
+    }
+
+    /// Adds an attribute.
+    ///
+    /// The value is not forced. If `name` was already inserted, the [`DuplicateAttrPolicy`] decides the outcome.
+    /// Returns an [`Err`] if `name` contains a null byte.
+    #[doc(alias = "nix_bindings_builder_insert")]
+    pub fn insert(&mut self, name: &str, value: Value) -> Result<()> {
+        let name = CString::new(name)
+            .with_context(|| "BindingsBuilder::insert: name contains null byte")?;
+        match self.attrs.entry(name) {
+            Entry::Occupied(mut entry) => match self.on_duplicate {
+                DuplicateAttrPolicy::Error => {
+                    bail!(
+                        "attribute `{}` already defined",
+                        entry.key().to_string_lossy()
+                    )
+                }
+                DuplicateAttrPolicy::Overwrite => {
+                    entry.insert(value);
+                }
+            },
+            Entry::Vacant(entry) => {
+                entry.insert(value);
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates the attribute set value.
+    #[doc(alias = "build")]
+    #[doc(alias = "nix_make_attrs")]
+    pub fn finish(mut self) -> Result<Value> {
+        let bindings_builder =
+            BindingsBuilderRef::new(&mut self.context, &self.eval_state, self.attrs.len())?;
+        for (name, value) in &self.attrs {
+            unsafe {
+                check_call!(raw::bindings_builder_insert(
+                    &mut self.context,
+                    bindings_builder.ptr,
+                    name.as_ptr(),
+                    value.raw_ptr()
+                ))?;
+            }
+        }
+        let value = unsafe {
+            let value = check_call!(raw::alloc_value(
+                &mut self.context,
+                self.eval_state.as_ptr()
+            ))?;
+            Value::new(value)
+        };
+        unsafe {
+            check_call!(raw::make_attrs(
+                &mut self.context,
+                value.raw_ptr(),
+                bindings_builder.ptr
+            ))?;
+        }
+        Ok(value)
+    }
+}
+
+// Internal RAII helper for the C bindings builder
+struct BindingsBuilderRef {
+    ptr: *mut raw::BindingsBuilder,
+}
+impl Drop for BindingsBuilderRef {
+    fn drop(&mut self) {
+        unsafe {
+            raw::bindings_builder_free(self.ptr);
+        }
+    }
+}
+impl BindingsBuilderRef {
+    fn new(context: &mut Context, eval_state: &EvalStateRef, capacity: usize) -> Result<Self> {
+        let ptr = unsafe {
+            check_call!(raw::make_bindings_builder(
+                context,
+                eval_state.as_ptr(),
+                capacity
+            ))
+        }?;
+        Ok(BindingsBuilderRef { ptr })
+    }
+}
+
+/// Triggers garbage collection immediately.
+#[doc(alias = "garbage_collect")]
+#[doc(alias = "collect")]
+#[doc(alias = "gc")]
+pub fn gc_now() {
+    unsafe {
+        raw::gc_now();
+    }
+}
+
+/// RAII guard for thread registration with the garbage collector.
+///
+/// Automatically unregisters the thread when dropped.
+pub struct ThreadRegistrationGuard {
+    must_unregister: bool,
+}
+impl Drop for ThreadRegistrationGuard {
+    fn drop(&mut self) {
+        if self.must_unregister {
+            unsafe {
+                gc::GC_unregister_my_thread();
+            }
+        }
+    }
+}
+
+fn gc_register_my_thread_do_it() -> Result<()> {
+    unsafe {
+        let mut sb: gc::GC_stack_base = gc::GC_stack_base {
+            mem_base: null_mut(),
+        };
+        let r = gc::GC_get_stack_base(&mut sb);
+        if r as u32 != gc::GC_SUCCESS {
+            Err(anyhow::format_err!("GC_get_stack_base failed: {}", r))?;
+        }
+        gc::GC_register_my_thread(&sb);
+        Ok(())
+    }
+}
+
+#[doc(alias = "register_thread")]
+#[doc(alias = "thread_setup")]
+#[doc(alias = "gc_register")]
+pub fn gc_register_my_thread() -> Result<ThreadRegistrationGuard> {
+    init()?;
+    unsafe {
+        let already_done = gc::GC_thread_is_registered();
+        if already_done != 0 {
+            return Ok(ThreadRegistrationGuard {
+                must_unregister: false,
+            });
+        }
+        gc_register_my_thread_do_it()?;
+        Ok(ThreadRegistrationGuard {
+            must_unregister: true,
+        })
+    }
+}
+
+impl Clone for EvalState {
+    fn clone(&self) -> Self {
+        EvalState {
+            eval_state: self.eval_state.clone(),
+            store: self.store.clone(),
             context: Context::new(),
         }
     }
@@ -1268,6 +1961,9 @@ mod tests {
     use std::io::Write as _;
     use std::sync::{Arc, Mutex};
 
+    /// Extra Nix configuration for a test that runs in a child process, see [`in_child_process_with_config`].
+    const EXTRA_CONFIG_VAR: &str = "NIX_BINDINGS_EXPR_TEST_EXTRA_CONFIG";
+
     #[ctor]
     fn setup() {
         test_init();
@@ -1276,7 +1972,11 @@ mod tests {
         // Set max-call-depth to 1000 (lower than default 10000) for the
         // eval_state_builder_loads_max_call_depth test case, while
         // giving other tests sufficient room for normal evaluation.
-        std::env::set_var("NIX_CONFIG", "max-call-depth = 1000");
+        let extra_config = std::env::var(EXTRA_CONFIG_VAR).unwrap_or_default();
+        std::env::set_var(
+            "NIX_CONFIG",
+            format!("max-call-depth = 1000\n{extra_config}"),
+        );
     }
 
     /// Run a function while making sure that the current thread is registered with the GC.
@@ -1374,6 +2074,23 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn eval_state_eval_helper_cached() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.eval_helper("test", "1").unwrap();
+            assert_eq!(es.require_int(&v).unwrap(), 1);
+            // The expression is not evaluated again, also not by a clone
+            let v = es.eval_helper("test", "2").unwrap();
+            assert_eq!(es.require_int(&v).unwrap(), 1);
+            let mut es2 = es.clone();
+            let v = es2.eval_helper("test", "2").unwrap();
+            assert_eq!(es2.require_int(&v).unwrap(), 1);
+        })
+        .unwrap();
+    }
+
     #[test]
     fn eval_state_value_bool() {
         gc_registering_current_thread(|| {
@@ -1409,6 +2126,154 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn eval_state_force_deep() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            // Keep a reference to the innermost thunk, so that its state can be inspected without forcing it
+            let inner = make_thunk(&mut es, "1 + 1");
+            let b = es.new_value_list([inner.clone()]).unwrap();
+            let a = es.new_value_attrs([("b".to_string(), b)]).unwrap();
+            let v = es.new_value_attrs([("a".to_string(), a)]).unwrap();
+            assert!(es.value_type_unforced(&inner).is_none());
+            es.force_deep(&v, None).unwrap();
+            assert!(es.value_type_unforced(&inner) == Some(ValueType::Int));
+
+            let v = es
+                .eval_from_string(r#"{ a = { b = [ (throw "deep") ]; }; }"#, "<test>")
+                .unwrap();
+            let e = es.force_deep(&v, None).unwrap_err();
+            assert!(e.to_string().contains("deep"));
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_force_deep_max_depth() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es
+                .eval_from_string(r#"{ a = { b = [ (throw "deep") ]; }; }"#, "<test>")
+                .unwrap();
+            es.force_deep(&v, Some(0)).unwrap();
+            es.force_deep(&v, Some(2)).unwrap();
+            let e = es.force_deep(&v, Some(3)).unwrap_err();
+            assert!(e.to_string().contains("deep"));
+
+            let v = make_thunk(&mut es, r#"throw "shallow""#);
+            assert!(es.force_deep(&v, Some(0)).is_err());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_force_deep_cycle() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es
+                .eval_from_string(
+                    "let x = { inherit x; l = [ x ]; y = 1 + 1; }; in x",
+                    "<test>",
+                )
+                .unwrap();
+            es.force_deep(&v, None).unwrap();
+            es.force_deep(&v, Some(usize::MAX)).unwrap();
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_force_deep_max_depth_shared() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            // Whichever attribute is visited first, `s` must be forced to the depth it has via `c`
+            for expr in ["{ a = { b = s; }; c = s; }", "{ a = s; c = { b = s; }; }"] {
+                let v = es
+                    .eval_from_string(
+                        &format!(r#"let s = {{ t = throw "deep"; }}; in {expr}"#),
+                        "<test>",
+                    )
+                    .unwrap();
+                let e = es.force_deep(&v, Some(2)).unwrap_err();
+                assert!(e.to_string().contains("deep"));
+            }
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_value_float() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.eval_from_string("1.5", "<test>").unwrap();
+            es.force(&v).unwrap();
+            let t = es.value_type(&v).unwrap();
+            assert!(t == ValueType::Float);
+            let f = es.require_float(&v).unwrap();
+            assert!(f == 1.5);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_value_float_special() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.eval_from_string("1.0e308 * 10.0", "<test>").unwrap();
+            let f = es.require_float(&v).unwrap();
+            assert!(f.is_infinite() && f.is_sign_positive());
+
+            let v = es
+                .eval_from_string("(1.0e308 * 10.0) - (1.0e308 * 10.0)", "<test>")
+                .unwrap();
+            let f = es.require_float(&v).unwrap();
+            assert!(f.is_nan());
+
+            // `-0.0` parses as `0 - 0.0`, which is positive zero.
+            let v = es.eval_from_string("0.0 * -1.0", "<test>").unwrap();
+            let f = es.require_float(&v).unwrap();
+            assert!(f == 0.0 && f.is_sign_negative());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_require_float_int() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.eval_from_string("1", "<test>").unwrap();
+            let r = es.require_float(&v);
+            match r {
+                Ok(_) => panic!("expected an error"),
+                Err(e) => assert_eq!(e.to_string(), "expected a float, but got a Int"),
+            }
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_require_float_forces_thunk() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let f = es.eval_from_string("x: x / 2.0", "<test>").unwrap();
+            let a = es.eval_from_string("3.0", "<test>").unwrap();
+            let v = es.new_value_apply(&f, &a).unwrap();
+            let t = es.value_type_unforced(&v);
+            assert!(t.is_none());
+            let f = es.require_float(&v).unwrap();
+            assert!(f == 1.5);
+        })
+        .unwrap();
+    }
+
     #[test]
     fn eval_state_require_int_forces_thunk() {
         gc_registering_current_thread(|| {
@@ -1551,6 +2416,52 @@ mod tests {
         .unwrap()
     }
 
+    #[test]
+    fn eval_state_require_attrs_select_missing_is_key_error() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.eval_from_string("{ a = 1; }", "<test>").unwrap();
+            match es.require_attrs_select(&v, "b") {
+                Ok(_) => panic!("expected an error"),
+                Err(e) => {
+                    assert_eq!(e.to_string(), "attribute `b` not found");
+                    let e = e.downcast_ref::<NixError>().unwrap();
+                    assert_eq!(e.kind(), NixErrorKind::Key);
+                }
+            }
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn eval_state_error_name() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+
+            match es.eval_from_string(r#"throw "oh no the error""#, "<test>") {
+                Ok(_) => panic!("expected an error"),
+                Err(e) => {
+                    let e = e.downcast_ref::<NixError>().unwrap();
+                    assert_eq!(e.kind(), NixErrorKind::NixError);
+                    assert_eq!(e.name(), Some("nix::ThrownError"));
+                    assert!(e.info_msg().unwrap().contains("oh no the error"));
+                }
+            }
+
+            match es.eval_from_string("assert false; 1", "<test>") {
+                Ok(_) => panic!("expected an error"),
+                Err(e) => {
+                    let e = e.downcast_ref::<NixError>().unwrap();
+                    assert_eq!(e.kind(), NixErrorKind::NixError);
+                    assert_eq!(e.name(), Some("nix::AssertionError"));
+                }
+            }
+        })
+        .unwrap()
+    }
+
     #[test]
     fn eval_state_require_attrs_select_forces_thunk() {
         gc_registering_current_thread(|| {
@@ -1723,84 +2634,204 @@ mod tests {
     }
 
     #[test]
-    fn eval_state_value_string_unexpected_context() {
+    fn eval_state_value_string_unexpected_context() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es
+                .eval_from_string("(derivation { name = \"hello\"; system = \"dummy\"; builder = \"cmd.exe\"; }).outPath", "<test>")
+                .unwrap();
+            es.force(&v).unwrap();
+            let t = es.value_type_unforced(&v);
+            assert!(t == Some(ValueType::String));
+            // TODO
+            // let r = es.require_string_without_context(&v);
+            // assert!(r.is_err());
+            // assert!(r.unwrap_err().to_string().contains("unexpected context"));
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_new_string() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.new_value_str("hello").unwrap();
+            es.force(&v).unwrap();
+            let t = es.value_type_unforced(&v);
+            assert!(t == Some(ValueType::String));
+            let s = es.require_string(&v).unwrap();
+            assert!(s == "hello");
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_new_string_empty() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.new_value_str("").unwrap();
+            es.force(&v).unwrap();
+            let t = es.value_type_unforced(&v);
+            assert!(t == Some(ValueType::String));
+            let s = es.require_string(&v).unwrap();
+            assert!(s.is_empty());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_new_string_invalid() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let r = es.new_value_str("hell\0no");
+            match r {
+                Ok(_) => panic!("expected an error"),
+                Err(e) => {
+                    if !e.to_string().contains("contains null byte") {
+                        eprintln!("{}", e);
+                        panic!();
+                    }
+                }
+            }
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_new_bool() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.new_value_bool(true).unwrap();
+            es.force(&v).unwrap();
+            let t = es.value_type_unforced(&v);
+            assert!(t == Some(ValueType::Bool));
+            assert!(es.require_bool(&v).unwrap());
+
+            let v = es.new_value_bool(false).unwrap();
+            assert!(!es.require_bool(&v).unwrap());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_new_null() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.new_value_null().unwrap();
+            es.force(&v).unwrap();
+            let t = es.value_type_unforced(&v);
+            assert!(t == Some(ValueType::Null));
+
+            let is_null = es.eval_from_string("x: x == null", "<test>").unwrap();
+            let r = es.call(is_null, v).unwrap();
+            assert!(es.require_bool(&r).unwrap());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_value_path() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.eval_from_string("/foo/bar", "<test>").unwrap();
+            let t = es.value_type(&v).unwrap();
+            assert!(t == ValueType::Path);
+            let p = es.require_path(&v).unwrap();
+            assert_eq!(p, "/foo/bar");
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_require_path_string() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.eval_from_string("\"/foo\"", "<test>").unwrap();
+            let r = es.require_path(&v);
+            match r {
+                Ok(_) => panic!("expected an error"),
+                Err(e) => assert_eq!(e.to_string(), "expected a path, but got a String"),
+            }
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_new_path() {
         gc_registering_current_thread(|| {
             let store = Store::open(None, HashMap::new()).unwrap();
             let mut es = EvalState::new(store, []).unwrap();
-            let v = es
-                .eval_from_string("(derivation { name = \"hello\"; system = \"dummy\"; builder = \"cmd.exe\"; }).outPath", "<test>")
-                .unwrap();
+            let v = es.new_value_path("/foo/bar").unwrap();
             es.force(&v).unwrap();
             let t = es.value_type_unforced(&v);
-            assert!(t == Some(ValueType::String));
-            // TODO
-            // let r = es.require_string_without_context(&v);
-            // assert!(r.is_err());
-            // assert!(r.unwrap_err().to_string().contains("unexpected context"));
+            assert!(t == Some(ValueType::Path));
+            let p = es.require_path(&v).unwrap();
+            assert_eq!(p, "/foo/bar");
+
+            // Behaves like a path literal
+            let f = es
+                .eval_from_string("p: p + \"/baz\" == /foo/bar/baz", "<test>")
+                .unwrap();
+            let r = es.call(f, v).unwrap();
+            assert!(es.require_bool(&r).unwrap());
         })
         .unwrap();
     }
 
     #[test]
-    fn eval_state_new_string() {
+    fn eval_state_new_int() {
         gc_registering_current_thread(|| {
             let store = Store::open(None, HashMap::new()).unwrap();
             let mut es = EvalState::new(store, []).unwrap();
-            let v = es.new_value_str("hello").unwrap();
+            let v = es.new_value_int(42).unwrap();
             es.force(&v).unwrap();
             let t = es.value_type_unforced(&v);
-            assert!(t == Some(ValueType::String));
-            let s = es.require_string(&v).unwrap();
-            assert!(s == "hello");
+            assert!(t == Some(ValueType::Int));
+            let i = es.require_int(&v).unwrap();
+            assert!(i == 42);
         })
         .unwrap();
     }
 
     #[test]
-    fn eval_state_new_string_empty() {
+    fn eval_state_new_float() {
         gc_registering_current_thread(|| {
             let store = Store::open(None, HashMap::new()).unwrap();
             let mut es = EvalState::new(store, []).unwrap();
-            let v = es.new_value_str("").unwrap();
+            let v = es.new_value_float(4.25).unwrap();
             es.force(&v).unwrap();
             let t = es.value_type_unforced(&v);
-            assert!(t == Some(ValueType::String));
-            let s = es.require_string(&v).unwrap();
-            assert!(s.is_empty());
+            assert!(t == Some(ValueType::Float));
+            let f = es.require_float(&v).unwrap();
+            assert!(f == 4.25);
         })
         .unwrap();
     }
 
     #[test]
-    fn eval_state_new_string_invalid() {
+    fn eval_state_new_float_special() {
         gc_registering_current_thread(|| {
             let store = Store::open(None, HashMap::new()).unwrap();
             let mut es = EvalState::new(store, []).unwrap();
-            let r = es.new_value_str("hell\0no");
-            match r {
-                Ok(_) => panic!("expected an error"),
-                Err(e) => {
-                    if !e.to_string().contains("contains null byte") {
-                        eprintln!("{}", e);
-                        panic!();
-                    }
-                }
+            for f in [f64::NAN, f64::INFINITY, f64::NEG_INFINITY, -0.0] {
+                let v = es.new_value_float(f).unwrap();
+                let g = es.require_float(&v).unwrap();
+                assert_eq!(f.to_bits(), g.to_bits());
             }
-        })
-        .unwrap();
-    }
 
-    #[test]
-    fn eval_state_new_int() {
-        gc_registering_current_thread(|| {
-            let store = Store::open(None, HashMap::new()).unwrap();
-            let mut es = EvalState::new(store, []).unwrap();
-            let v = es.new_value_int(42).unwrap();
-            es.force(&v).unwrap();
-            let t = es.value_type_unforced(&v);
-            assert!(t == Some(ValueType::Int));
-            let i = es.require_int(&v).unwrap();
-            assert!(i == 42);
+            // Nix sees them as floats too
+            let is_nan = es.eval_from_string("x: x != x", "<test>").unwrap();
+            let v = es.new_value_float(f64::NAN).unwrap();
+            let r = es.call(is_nan, v).unwrap();
+            assert!(es.require_bool(&r).unwrap());
         })
         .unwrap();
     }
@@ -2432,6 +3463,281 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn eval_state_new_value_list() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let a = es.new_value_int(1).unwrap();
+            let b = es.new_value_str("two").unwrap();
+            let v = es.new_value_list([a, b]).unwrap();
+            es.force(&v).unwrap();
+            let t = es.value_type_unforced(&v);
+            assert!(t == Some(ValueType::List));
+            let r: Vec<Value> = es.require_list_strict(&v).unwrap();
+            assert_eq!(r.len(), 2);
+            assert_eq!(es.require_int(&r[0]).unwrap(), 1);
+            assert_eq!(es.require_string(&r[1]).unwrap(), "two");
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_new_value_list_empty() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.new_value_list(Vec::new()).unwrap();
+            assert_eq!(es.require_list_size(&v).unwrap(), 0);
+            let f = es.eval_from_string("x: x == [ ]", "<test>").unwrap();
+            let r = es.call(f, v).unwrap();
+            assert!(es.require_bool(&r).unwrap());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_new_value_list_lazy() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let a = es
+                .eval_from_string("throw \"not forced\"", "<test>")
+                .unwrap();
+            let b = es.new_value_int(2).unwrap();
+            let v = es.new_value_list([a, b]).unwrap();
+            assert_eq!(es.require_list_size(&v).unwrap(), 2);
+            let e = es.require_list_select_idx_strict(&v, 1).unwrap().unwrap();
+            assert_eq!(es.require_int(&e).unwrap(), 2);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_list_builder() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let mut builder = ListBuilder::new(&mut es, 3).unwrap();
+            assert!(builder.is_empty());
+            for i in 0..3 {
+                let v = es.new_value_int(i).unwrap();
+                builder.push(&v).unwrap();
+            }
+            assert_eq!(builder.len(), 3);
+            let extra = es.new_value_int(3).unwrap();
+            assert!(builder.push(&extra).is_err());
+            let v = builder.finish().unwrap();
+            let f = es.eval_from_string("x: x == [ 0 1 2 ]", "<test>").unwrap();
+            let r = es.call(f, v).unwrap();
+            assert!(es.require_bool(&r).unwrap());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_list_builder_incomplete() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let mut builder = ListBuilder::new(&mut es, 2).unwrap();
+            let v = es.new_value_int(1).unwrap();
+            builder.push(&v).unwrap();
+            match builder.finish() {
+                Ok(_) => panic!("expected an error"),
+                Err(e) => assert_eq!(e.to_string(), "ListBuilder: expected 2 elements, but got 1"),
+            }
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_attrs_iter_strict() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = make_thunk(&mut es, "{ a = 1; b = 1 + 1; }");
+            let iter = es.attrs_iter_strict(&v).unwrap();
+            assert_eq!(iter.len(), 2);
+            let mut attrs: Vec<(String, Value)> = iter.collect::<Result<_>>().unwrap();
+            attrs.sort_by(|a, b| a.0.cmp(&b.0));
+            assert_eq!(attrs[0].0, "a");
+            assert_eq!(attrs[1].0, "b");
+            // forced by attrs_iter_strict
+            let t = es.value_type_unforced(&attrs[1].1);
+            assert!(t == Some(ValueType::Int));
+            assert_eq!(es.require_int(&attrs[1].1).unwrap(), 2);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_attrs_iter_empty() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.eval_from_string("{ }", "<test>").unwrap();
+            let mut iter = es.attrs_iter_strict(&v).unwrap();
+            assert_eq!(iter.len(), 0);
+            assert!(iter.next().is_none());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_attrs_iter_not_attrs() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es.eval_from_string("[ ]", "<test>").unwrap();
+            match es.attrs_iter_strict(&v) {
+                Ok(_) => panic!("expected an error"),
+                Err(e) => assert_eq!(e.to_string(), "expected an attrset, but got a List"),
+            }
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_attrs_iter_error() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es
+                .eval_from_string("{ a = throw \"oh no\"; }", "<test>")
+                .unwrap();
+            let mut iter = es.attrs_iter_strict(&v).unwrap();
+            match iter.next() {
+                Some(Err(e)) => assert!(e.to_string().contains("oh no")),
+                _ => panic!("expected an error"),
+            }
+        })
+        .unwrap();
+    }
+
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn eval_state_attrs_iter_lazy() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = es
+                .eval_from_string("{ a = throw \"oh no\"; b = 1 + 1; }", "<test>")
+                .unwrap();
+            let mut attrs: Vec<(String, Value)> = es
+                .attrs_iter_lazy(&v)
+                .unwrap()
+                .collect::<Result<_>>()
+                .unwrap();
+            attrs.sort_by(|a, b| a.0.cmp(&b.0));
+            assert_eq!(attrs[0].0, "a");
+            assert!(es.value_type_unforced(&attrs[0].1).is_none());
+            assert!(es.require_int(&attrs[0].1).is_err());
+            assert_eq!(es.require_int(&attrs[1].1).unwrap(), 2);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_bindings_builder() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let mut builder = BindingsBuilder::with_capacity(&es, 1);
+            assert!(builder.is_empty());
+            // grows beyond the initial capacity
+            for i in 0..100 {
+                let v = es.new_value_int(i).unwrap();
+                builder.insert(&format!("a{}", i), v).unwrap();
+            }
+            assert_eq!(builder.len(), 100);
+            assert!(builder.contains("a42"));
+            assert!(!builder.contains("a100"));
+            let v = builder.finish().unwrap();
+            let t = es.value_type_unforced(&v);
+            assert!(t == Some(ValueType::AttrSet));
+            assert_eq!(es.require_attrs_names(&v).unwrap().len(), 100);
+            let a42 = es.require_attrs_select(&v, "a42").unwrap();
+            assert_eq!(es.require_int(&a42).unwrap(), 42);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_bindings_builder_empty() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let v = BindingsBuilder::new(&es).finish().unwrap();
+            let f = es.eval_from_string("x: x == { }", "<test>").unwrap();
+            let r = es.call(f, v).unwrap();
+            assert!(es.require_bool(&r).unwrap());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_bindings_builder_duplicate_error() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let mut builder = BindingsBuilder::new(&es);
+            let a = es.new_value_int(1).unwrap();
+            let b = es.new_value_int(2).unwrap();
+            builder.insert("a", a).unwrap();
+            let r = builder.insert("a", b);
+            match r {
+                Ok(_) => panic!("expected an error"),
+                Err(e) => assert_eq!(e.to_string(), "attribute `a` already defined"),
+            }
+            // The first value is kept
+            let v = builder.finish().unwrap();
+            let a = es.require_attrs_select(&v, "a").unwrap();
+            assert_eq!(es.require_int(&a).unwrap(), 1);
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_bindings_builder_duplicate_overwrite() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let mut builder =
+                BindingsBuilder::new(&es).on_duplicate(DuplicateAttrPolicy::Overwrite);
+            let a = es.new_value_int(1).unwrap();
+            let b = es.new_value_int(2).unwrap();
+            let c = es.new_value_int(3).unwrap();
+            builder.insert("a", a).unwrap();
+            builder.insert("b", b).unwrap();
+            builder.insert("a", c).unwrap();
+            assert_eq!(builder.len(), 2);
+            let v = builder.finish().unwrap();
+            let f = es
+                .eval_from_string("x: x == { a = 3; b = 2; }", "<test>")
+                .unwrap();
+            let r = es.call(f, v).unwrap();
+            assert!(es.require_bool(&r).unwrap());
+        })
+        .unwrap();
+    }
+
+    #[test]
+    fn eval_state_bindings_builder_invalid_name() {
+        gc_registering_current_thread(|| {
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalState::new(store, []).unwrap();
+            let mut builder = BindingsBuilder::new(&es);
+            let a = es.new_value_int(1).unwrap();
+            let r = builder.insert("a\0b", a);
+            match r {
+                Ok(_) => panic!("expected an error"),
+                Err(e) => assert!(e.to_string().contains("contains null byte")),
+            }
+        })
+        .unwrap();
+    }
+
     #[test]
     pub fn eval_state_new_value_attrs_from_slice_empty() {
         gc_registering_current_thread(|| {
@@ -2847,4 +4153,130 @@ mod tests {
         })
         .unwrap();
     }
+
+    /// Runs the test `name` again in a child process, with `config` added to its ambient Nix configuration.
+    ///
+    /// Returns `true` in the child process, where the test should do its work, and `false` in the parent, after the child succeeded.
+    /// This way, settings that would affect other tests are not set in this process.
+    #[cfg(nix_at_least = "2.26")]
+    fn in_child_process_with_config(name: &str, config: &str) -> bool {
+        if std::env::var_os(EXTRA_CONFIG_VAR).is_some() {
+            return true;
+        }
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([name, "--exact", "--test-threads=1"])
+            .env(EXTRA_CONFIG_VAR, config)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success() && stdout.contains("1 passed"),
+            "{}\n{}",
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        false
+    }
+
+    #[cfg(nix_at_least = "2.26")]
+    fn assert_error_kind(r: Result<Value>, kind: NixErrorKind) {
+        match r {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => {
+                let nix_error = e.downcast_ref::<NixError>().unwrap();
+                assert_eq!(nix_error.kind(), kind, "{}", e);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(nix_at_least = "2.26")]
+    fn eval_state_restrict_eval() {
+        if !in_child_process_with_config(
+            "eval_state::tests::eval_state_restrict_eval",
+            "restrict-eval = true",
+        ) {
+            return;
+        }
+        gc_registering_current_thread(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let file = dir.path().join("file.txt");
+            std::fs::write(&file, "secret").unwrap();
+            let expr = format!("builtins.readFile {}", file.to_str().unwrap());
+
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalStateBuilder::new(store.clone())
+                .unwrap()
+                .build()
+                .unwrap();
+            assert_error_kind(
+                es.eval_from_string(&expr, "<test>"),
+                NixErrorKind::Restricted,
+            );
+            assert_error_kind(
+                es.eval_from_string(
+                    r#"builtins.fetchurl "https://example.com/file.txt""#,
+                    "<test>",
+                ),
+                NixErrorKind::Restricted,
+            );
+            assert_error_kind(
+                es.eval_from_string(r#"throw "nope""#, "<test>"),
+                NixErrorKind::NixError,
+            );
+
+            // Lookup path entries are allowed
+            let lookup_path = format!("secrets={}", dir.path().to_str().unwrap());
+            let mut es = EvalStateBuilder::new(store)
+                .unwrap()
+                .lookup_path([lookup_path.as_str()])
+                .unwrap()
+                .build()
+                .unwrap();
+            let v = es.eval_from_string(&expr, "<test>").unwrap();
+            assert_eq!(es.require_string(&v).unwrap(), "secret");
+        })
+        .unwrap();
+    }
+
+    #[test]
+    #[cfg(nix_at_least = "2.26")]
+    fn eval_state_pure_eval() {
+        if !in_child_process_with_config(
+            "eval_state::tests::eval_state_pure_eval",
+            "pure-eval = true",
+        ) {
+            return;
+        }
+        gc_registering_current_thread(|| {
+            let dir = tempfile::tempdir().unwrap();
+            let file = dir.path().join("file.txt");
+            std::fs::write(&file, "secret").unwrap();
+            let expr = format!("builtins.readFile {}", file.to_str().unwrap());
+
+            // Pure evaluation does not use the lookup path
+            let lookup_path = format!("secrets={}", dir.path().to_str().unwrap());
+            let store = Store::open(None, HashMap::new()).unwrap();
+            let mut es = EvalStateBuilder::new(store)
+                .unwrap()
+                .lookup_path([lookup_path.as_str()])
+                .unwrap()
+                .build()
+                .unwrap();
+            assert_error_kind(
+                es.eval_from_string(&expr, "<test>"),
+                NixErrorKind::Restricted,
+            );
+            // Only path and URI accesses are classified as Restricted
+            assert_error_kind(
+                es.eval_from_string("<secrets>", "<test>"),
+                NixErrorKind::NixError,
+            );
+            let v = es
+                .eval_from_string("builtins ? currentTime", "<test>")
+                .unwrap();
+            assert!(!es.require_bool(&v).unwrap());
+        })
+        .unwrap();
+    }
 }
@@ -0,0 +1,216 @@
+//! # Converting values to and from JSON
+//!
+//! [`EvalState::to_json`] and [`EvalState::from_json`] convert between Nix values and [`serde_json::Value`].
+//!
+//! Requires the `serde` feature.
+
+use crate::eval_state::EvalState;
+use crate::value::Value;
+use anyhow::{Context as _, Result};
+use nix_bindings_store::path::StorePath;
+
+/// A JSON value together with the [string context](https://nix.dev/manual/nix/latest/language/string-context.html) of the Nix string it was produced from.
+///
+/// Returned by [`EvalState::to_json`].
+pub struct JsonWithContext {
+    /// The JSON value.
+    pub json: serde_json::Value,
+    /// Store paths referenced by the JSON value.
+    ///
+    /// For a derivation output, this is the path of the derivation (`.drv`), because the output may not have been built yet, and its path may not be known.
+    pub paths: Vec<StorePath>,
+}
+
+impl EvalState {
+    /// Converts a value to JSON, following the semantics of [`builtins.toJSON`](https://nix.dev/manual/nix/latest/language/builtins.html#builtins-toJSON).
+    ///
+    /// The value is evaluated deeply. Attribute sets with `__toString` or `outPath` are coerced to strings, and path values are copied to the store.
+    /// The store paths that the resulting JSON refers to are returned as well, without building anything.
+    ///
+    /// Returns an [`Err`] if the value can not be represented as JSON, for example if it contains a function.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use nix_bindings_expr::eval_state::{EvalState, test_init, gc_register_my_thread};
+    /// # use nix_bindings_store::store::Store;
+    /// # use std::collections::HashMap;
+    /// # fn example() -> anyhow::Result<()> {
+    /// # test_init();
+    /// # let guard = gc_register_my_thread()?;
+    /// let store = Store::open(None, HashMap::new())?;
+    /// let mut es = EvalState::new(store, [])?;
+    ///
+    /// let v = es.eval_from_string(r#"{ file = builtins.toFile "hello.txt" "hello"; n = 1; }"#, "<example>")?;
+    /// let r = es.to_json(&v)?;
+    /// assert_eq!(r.json["n"], 1);
+    /// assert_eq!(r.paths[0].name()?, "hello.txt");
+    /// # drop(guard);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[doc(alias = "toJSON")]
+    #[doc(alias = "serialize")]
+    pub fn to_json(&mut self, value: &Value) -> Result<JsonWithContext> {
+        let f = self.eval_helper(
+            "to_json",
+            "v: let s = builtins.toJSON v; in [ s (builtins.attrNames (builtins.getContext s)) ]",
+        )?;
+        let r = self.call(f, value.clone())?;
+        let r: Vec<Value> = self.require_list_strict(&r)?;
+        let [s, context] = <[Value; 2]>::try_from(r)
+            .map_err(|_| anyhow::format_err!("to_json: expected a list of two elements"))?;
+
+        let s = self.require_string(&s)?;
+        let json = serde_json::from_str(&s).context("to_json: Nix produced invalid JSON")?;
+
+        let context: Vec<Value> = self.require_list_strict(&context)?;
+        let mut store = self.store().clone();
+        let paths = context
+            .iter()
+            .map(|path| {
+                let path = self.require_string(path)?;
+                store.parse_store_path(&path)
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(JsonWithContext { json, paths })
+    }
+
+    /// Creates a Nix value from JSON, like [`builtins.fromJSON`](https://nix.dev/manual/nix/latest/language/builtins.html#builtins-fromJSON).
+    ///
+    /// Objects become attribute sets and arrays become lists.
+    /// Numbers become integers if they are integers that fit in a Nix integer, and floats otherwise.
+    ///
+    /// The result has no [string context](https://nix.dev/manual/nix/latest/language/string-context.html),
+    /// so this is only the inverse of [`to_json`][`EvalState::to_json`] for values that don't refer to store paths.
+    #[doc(alias = "fromJSON")]
+    #[doc(alias = "deserialize")]
+    pub fn from_json(&mut self, json: &serde_json::Value) -> Result<Value> {
+        Ok(crate::ser::to_value(self, &JsonNumbersAsFloats(json))?)
+    }
+}
+
+/// Serializes JSON numbers that don't fit in a Nix integer as floats, like `builtins.fromJSON`.
+struct JsonNumbersAsFloats<'a>(&'a serde_json::Value);
+impl serde::Serialize for JsonNumbersAsFloats<'_> {
+    fn serialize<S: serde::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::{SerializeMap, SerializeSeq};
+        match self.0 {
+            serde_json::Value::Number(n) => match n.as_i64() {
+                Some(i) => s.serialize_i64(i),
+                None => s.serialize_f64(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            serde_json::Value::Array(items) => {
+                let mut seq = s.serialize_seq(Some(items.len()))?;
+                for item in items {
+                    seq.serialize_element(&JsonNumbersAsFloats(item))?;
+                }
+                seq.end()
+            }
+            serde_json::Value::Object(map) => {
+                let mut m = s.serialize_map(Some(map.len()))?;
+                for (k, v) in map {
+                    m.serialize_entry(k, &JsonNumbersAsFloats(v))?;
+                }
+                m.end()
+            }
+            v => v.serialize(s),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval_state::gc_register_my_thread;
+    use nix_bindings_store::store::Store;
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    fn to_json(expr: &str) -> Result<JsonWithContext> {
+        let guard = gc_register_my_thread().unwrap();
+        let store = Store::open(None, HashMap::new()).unwrap();
+        let mut es = EvalState::new(store, []).unwrap();
+        let v = es.eval_from_string(expr, "<test>").unwrap();
+        let r = es.to_json(&v);
+        drop(guard);
+        r
+    }
+
+    #[test]
+    fn to_json_simple() {
+        let r = to_json(r#"{ a = 1; b = [ true null "x" 1.5 ]; c.d = -2; }"#).unwrap();
+        assert_eq!(
+            r.json,
+            json!({ "a": 1, "b": [true, null, "x", 1.5], "c": { "d": -2 } })
+        );
+        assert!(r.paths.is_empty());
+    }
+
+    #[test]
+    fn to_json_coercions() {
+        let r = to_json(
+            r#"{ s = { __toString = self: "hi ${self.x}"; x = "there"; }; o = { outPath = "/foo"; }; }"#,
+        )
+        .unwrap();
+        assert_eq!(r.json, json!({ "s": "hi there", "o": "/foo" }));
+    }
+
+    #[test]
+    fn to_json_context() {
+        let r = to_json(
+            r#"{
+                drv = derivation {
+                    name = "not-built";
+                    system = builtins.currentSystem;
+                    builder = "/bin/sh";
+                    args = [ "-c" "echo foo > $out" ];
+                };
+                file = builtins.toFile "just-a-file" "ooh file good";
+                plain = "no context";
+            }"#,
+        )
+        .unwrap();
+        assert!(r.json["drv"].as_str().unwrap().ends_with("-not-built"));
+        assert!(r.json["file"].as_str().unwrap().ends_with("-just-a-file"));
+        let mut names: Vec<String> = r.paths.iter().map(|p| p.name().unwrap()).collect();
+        names.sort();
+        assert_eq!(names, ["just-a-file", "not-built.drv"]);
+    }
+
+    #[test]
+    fn to_json_function() {
+        match to_json("{ f = x: x; }") {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => assert!(e.to_string().contains("function"), "{}", e),
+        }
+    }
+
+    #[test]
+    fn from_json() {
+        let guard = gc_register_my_thread().unwrap();
+        let store = Store::open(None, HashMap::new()).unwrap();
+        let mut es = EvalState::new(store, []).unwrap();
+        let json = json!({
+            "a": [1, 2.5, "three", null, true],
+            "b": { "c": {} },
+            "big": 18446744073709551615u64,
+        });
+        let v = es.from_json(&json).unwrap();
+        let f = es
+            .eval_from_string(
+                r#"v: v == { a = [ 1 2.5 "three" null true ]; b.c = { }; big = 18446744073709551615.0; }"#,
+                "<test>",
+            )
+            .unwrap();
+        let r = es.call(f, v.clone()).unwrap();
+        assert!(es.require_bool(&r).unwrap());
+
+        // round trip
+        let r = es.to_json(&v).unwrap();
+        assert_eq!(r.json["a"], json["a"]);
+        assert_eq!(r.json["b"], json["b"]);
+        drop(guard);
+    }
+}
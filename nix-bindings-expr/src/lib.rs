@@ -1,3 +1,10 @@
+#[cfg(feature = "serde")]
+pub mod de;
 pub mod eval_state;
+#[cfg(feature = "serde")]
+pub mod json;
 pub mod primop;
+#[cfg(feature = "serde")]
+pub mod ser;
+pub mod show;
 pub mod value;
@@ -0,0 +1,813 @@
+//! # Serializing Rust values to Nix values with serde
+//!
+//! This module implements [`serde::Serializer`], so that any Rust type that implements [`serde::Serialize`] can be turned into a Nix [`Value`],
+//! for example to pass it as an argument to [`EvalState::call`].
+//!
+//! Requires the `serde` feature.
+//!
+//! Errors report the location of the offending value, e.g. `services.nginx.port: integer 18446744073709551615 does not fit in a Nix integer`.
+//!
+//! ## Type mapping
+//!
+//! - `bool`, integers, floats and strings map to the corresponding Nix types.
+//!   Integers must fit in a Nix integer, which is 64 bits and signed.
+//! - `None`, `()` and unit structs map to `null`.
+//! - Sequences, tuples and byte arrays map to lists.
+//! - Maps and structs map to attribute sets. Map keys must be strings, characters, integers or unit enum variants.
+//! - Unit enum variants map to strings, and other enum variants to an attribute set with a single attribute named after the variant.
+//!
+//! This is the inverse of the mapping in [`de`][`crate::de`].
+//!
+//! ## Example
+//!
+//! ```rust
+//! # use nix_bindings_expr::eval_state::{EvalState, test_init, gc_register_my_thread};
+//! # use nix_bindings_store::store::Store;
+//! # use std::collections::HashMap;
+//! #[derive(serde::Serialize)]
+//! struct Nginx {
+//!     enable: bool,
+//!     port: u16,
+//! }
+//!
+//! # fn example() -> anyhow::Result<()> {
+//! # test_init();
+//! # let guard = gc_register_my_thread()?;
+//! let store = Store::open(None, HashMap::new())?;
+//! let mut es = EvalState::new(store, [])?;
+//!
+//! let arg = nix_bindings_expr::ser::to_value(&mut es, &Nginx { enable: true, port: 80 })?;
+//! let f = es.eval_from_string("cfg: cfg.port + 1", "<example>")?;
+//! let v = es.call(f, arg)?;
+//! assert_eq!(es.require_int(&v)?, 81);
+//! # drop(guard);
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::de::AttrPath;
+use crate::eval_state::{BindingsBuilder, EvalState};
+use crate::value::{Int, Value};
+use serde::ser::{self, Serialize};
+use std::fmt;
+
+pub use crate::de::Error;
+
+impl ser::Error for Error {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        <Error as serde::de::Error>::custom(msg)
+    }
+}
+
+/// Serializes a Rust value to a Nix value.
+///
+/// See the [module documentation][`self`] for details.
+pub fn to_value<T>(eval_state: &mut EvalState, value: &T) -> Result<Value, Error>
+where
+    T: Serialize + ?Sized,
+{
+    value.serialize(Serializer::new(eval_state))
+}
+
+fn int<T>(i: T) -> Result<Int, Error>
+where
+    T: TryInto<Int> + fmt::Display + Copy,
+{
+    i.try_into().map_err(|_| {
+        <Error as ser::Error>::custom(format_args!("integer {} does not fit in a Nix integer", i))
+    })
+}
+
+/// A [`serde::Serializer`] that produces Nix [`Value`]s.
+///
+/// Most callers will want to use [`to_value`] instead.
+pub struct Serializer<'a> {
+    eval_state: &'a mut EvalState,
+    path: AttrPath,
+}
+impl<'a> Serializer<'a> {
+    /// Creates a serializer that allocates values in `eval_state`.
+    pub fn new(eval_state: &'a mut EvalState) -> Self {
+        Serializer {
+            eval_state,
+            path: AttrPath::default(),
+        }
+    }
+
+    /// Serializes `value`, which is at `path` relative to the root value, and reports errors at that location.
+    fn serialize_at<T: Serialize + ?Sized>(
+        eval_state: &mut EvalState,
+        path: AttrPath,
+        value: &T,
+    ) -> Result<Value, Error> {
+        let serializer = Serializer {
+            eval_state,
+            path: path.clone(),
+        };
+        value.serialize(serializer).map_err(|e| e.or_at(&path))
+    }
+
+    fn eval_err(&self, e: anyhow::Error) -> Error {
+        Error::eval(&self.path, e)
+    }
+
+    /// Wraps a value in a single-attribute attribute set, for enum variants.
+    fn variant(&mut self, variant: &str, value: Value) -> Result<Value, Error> {
+        let mut builder = BindingsBuilder::with_capacity(self.eval_state, 1);
+        builder
+            .insert(variant, value)
+            .map_err(|e| Error::eval(&self.path.attr(variant), e))?;
+        builder.finish().map_err(|e| self.eval_err(e))
+    }
+}
+
+impl<'a> ser::Serializer for Serializer<'a> {
+    type Ok = Value;
+    type Error = Error;
+
+    type SerializeSeq = SeqSerializer<'a>;
+    type SerializeTuple = SeqSerializer<'a>;
+    type SerializeTupleStruct = SeqSerializer<'a>;
+    type SerializeTupleVariant = SeqSerializer<'a>;
+    type SerializeMap = MapSerializer<'a>;
+    type SerializeStruct = MapSerializer<'a>;
+    type SerializeStructVariant = MapSerializer<'a>;
+
+    fn serialize_bool(self, v: bool) -> Result<Value, Error> {
+        self.eval_state
+            .new_value_bool(v)
+            .map_err(|e| self.eval_err(e))
+    }
+
+    fn serialize_i8(self, v: i8) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i16(self, v: i16) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i32(self, v: i32) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_i64(self, v: i64) -> Result<Value, Error> {
+        self.eval_state
+            .new_value_int(v)
+            .map_err(|e| self.eval_err(e))
+    }
+    fn serialize_i128(self, v: i128) -> Result<Value, Error> {
+        self.serialize_i64(int(v)?)
+    }
+    fn serialize_u8(self, v: u8) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_u16(self, v: u16) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_u32(self, v: u32) -> Result<Value, Error> {
+        self.serialize_i64(v.into())
+    }
+    fn serialize_u64(self, v: u64) -> Result<Value, Error> {
+        self.serialize_i64(int(v)?)
+    }
+    fn serialize_u128(self, v: u128) -> Result<Value, Error> {
+        self.serialize_i64(int(v)?)
+    }
+
+    fn serialize_f32(self, v: f32) -> Result<Value, Error> {
+        self.serialize_f64(v.into())
+    }
+    fn serialize_f64(self, v: f64) -> Result<Value, Error> {
+        self.eval_state
+            .new_value_float(v)
+            .map_err(|e| self.eval_err(e))
+    }
+
+    fn serialize_char(self, v: char) -> Result<Value, Error> {
+        self.serialize_str(v.encode_utf8(&mut [0; 4]))
+    }
+    fn serialize_str(self, v: &str) -> Result<Value, Error> {
+        self.eval_state
+            .new_value_str(v)
+            .map_err(|e| self.eval_err(e))
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, Error> {
+        let items = v
+            .iter()
+            .map(|b| self.eval_state.new_value_int((*b).into()))
+            .collect::<anyhow::Result<Vec<_>>>();
+        let items = items.map_err(|e| self.eval_err(e))?;
+        self.eval_state
+            .new_value_list(items)
+            .map_err(|e| self.eval_err(e))
+    }
+
+    fn serialize_none(self) -> Result<Value, Error> {
+        self.serialize_unit()
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, Error> {
+        self.eval_state
+            .new_value_null()
+            .map_err(|e| self.eval_err(e))
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, Error> {
+        self.serialize_unit()
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<Value, Error> {
+        self.serialize_str(variant)
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        value.serialize(self)
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        mut self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, Error> {
+        let path = self.path.attr(variant);
+        let value = Serializer::serialize_at(self.eval_state, path, value)?;
+        self.variant(variant, value)
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer<'a>, Error> {
+        Ok(SeqSerializer {
+            eval_state: self.eval_state,
+            items: Vec::with_capacity(len.unwrap_or(0)),
+            variant: None,
+            path: self.path,
+        })
+    }
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer<'a>, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer<'a>, Error> {
+        self.serialize_seq(Some(len))
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer<'a>, Error> {
+        let mut s = self.serialize_seq(Some(len))?;
+        s.variant = Some(variant);
+        Ok(s)
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<MapSerializer<'a>, Error> {
+        let builder = BindingsBuilder::with_capacity(self.eval_state, len.unwrap_or(0));
+        Ok(MapSerializer {
+            eval_state: self.eval_state,
+            builder,
+            key: None,
+            variant: None,
+            path: self.path,
+        })
+    }
+    fn serialize_struct(self, _name: &'static str, len: usize) -> Result<MapSerializer<'a>, Error> {
+        self.serialize_map(Some(len))
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<MapSerializer<'a>, Error> {
+        let mut s = self.serialize_map(Some(len))?;
+        s.variant = Some(variant);
+        Ok(s)
+    }
+}
+
+/// Serializes sequences, tuples and tuple variants to a list.
+pub struct SeqSerializer<'a> {
+    eval_state: &'a mut EvalState,
+    items: Vec<Value>,
+    variant: Option<&'static str>,
+    path: AttrPath,
+}
+impl SeqSerializer<'_> {
+    /// The location of the list, which is inside the variant attribute for tuple variants.
+    fn list_path(&self) -> AttrPath {
+        match self.variant {
+            Some(variant) => self.path.attr(variant),
+            None => self.path.clone(),
+        }
+    }
+
+    fn push<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let path = self.list_path().index(self.items.len() as u32);
+        let value = Serializer::serialize_at(self.eval_state, path, value)?;
+        self.items.push(value);
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        let list_path = self.list_path();
+        let list = self
+            .eval_state
+            .new_value_list(self.items)
+            .map_err(|e| Error::eval(&list_path, e))?;
+        let mut s = Serializer {
+            eval_state: self.eval_state,
+            path: self.path,
+        };
+        match self.variant {
+            Some(variant) => s.variant(variant, list),
+            None => Ok(list),
+        }
+    }
+}
+impl ser::SerializeSeq for SeqSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+impl ser::SerializeTuple for SeqSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+impl ser::SerializeTupleStruct for SeqSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+impl ser::SerializeTupleVariant for SeqSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        self.push(value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+/// Serializes maps, structs and struct variants to an attribute set.
+pub struct MapSerializer<'a> {
+    eval_state: &'a mut EvalState,
+    builder: BindingsBuilder,
+    key: Option<String>,
+    variant: Option<&'static str>,
+    path: AttrPath,
+}
+impl MapSerializer<'_> {
+    /// The location of the attribute set, which is inside the variant attribute for struct variants.
+    fn attrs_path(&self) -> AttrPath {
+        match self.variant {
+            Some(variant) => self.path.attr(variant),
+            None => self.path.clone(),
+        }
+    }
+
+    fn insert<T: Serialize + ?Sized>(&mut self, key: &str, value: &T) -> Result<(), Error> {
+        let path = self.attrs_path().attr(key);
+        let value = Serializer::serialize_at(self.eval_state, path.clone(), value)?;
+        self.builder
+            .insert(key, value)
+            .map_err(|e| Error::eval(&path, e))
+    }
+
+    fn finish(self) -> Result<Value, Error> {
+        let attrs_path = self.attrs_path();
+        let attrs = self
+            .builder
+            .finish()
+            .map_err(|e| Error::eval(&attrs_path, e))?;
+        let mut s = Serializer {
+            eval_state: self.eval_state,
+            path: self.path,
+        };
+        match self.variant {
+            Some(variant) => s.variant(variant, attrs),
+            None => Ok(attrs),
+        }
+    }
+}
+impl ser::SerializeMap for MapSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), Error> {
+        let key = key
+            .serialize(MapKeySerializer)
+            .map_err(|e| e.or_at(&self.attrs_path()))?;
+        self.key = Some(key);
+        Ok(())
+    }
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), Error> {
+        let key = self.key.take().ok_or_else(|| {
+            <Error as ser::Error>::custom("serialize_value called before serialize_key")
+        })?;
+        self.insert(&key, value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+impl ser::SerializeStruct for MapSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key, value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+impl ser::SerializeStructVariant for MapSerializer<'_> {
+    type Ok = Value;
+    type Error = Error;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), Error> {
+        self.insert(key, value)
+    }
+    fn end(self) -> Result<Value, Error> {
+        self.finish()
+    }
+}
+
+/// Turns map keys into attribute names.
+struct MapKeySerializer;
+
+fn key_must_be_a_string() -> Error {
+    <Error as ser::Error>::custom("attribute names must be strings")
+}
+
+impl ser::Serializer for MapKeySerializer {
+    type Ok = String;
+    type Error = Error;
+
+    type SerializeSeq = ser::Impossible<String, Error>;
+    type SerializeTuple = ser::Impossible<String, Error>;
+    type SerializeTupleStruct = ser::Impossible<String, Error>;
+    type SerializeTupleVariant = ser::Impossible<String, Error>;
+    type SerializeMap = ser::Impossible<String, Error>;
+    type SerializeStruct = ser::Impossible<String, Error>;
+    type SerializeStructVariant = ser::Impossible<String, Error>;
+
+    fn serialize_str(self, v: &str) -> Result<String, Error> {
+        Ok(v.to_owned())
+    }
+    fn serialize_char(self, v: char) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+    fn serialize_i8(self, v: i8) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+    fn serialize_i16(self, v: i16) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+    fn serialize_i32(self, v: i32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+    fn serialize_i64(self, v: i64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+    fn serialize_i128(self, v: i128) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+    fn serialize_u8(self, v: u8) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+    fn serialize_u16(self, v: u16) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+    fn serialize_u32(self, v: u32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+    fn serialize_u64(self, v: u64) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+    fn serialize_u128(self, v: u128) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        variant: &'static str,
+    ) -> Result<String, Error> {
+        Ok(variant.to_owned())
+    }
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<String, Error> {
+        value.serialize(self)
+    }
+
+    fn serialize_bool(self, _v: bool) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_f32(self, _v: f32) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_f64(self, _v: f64) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_bytes(self, _v: &[u8]) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_none(self) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_some<T: Serialize + ?Sized>(self, _value: &T) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_unit(self) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _value: &T,
+    ) -> Result<String, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_tuple(self, _len: usize) -> Result<Self::SerializeTuple, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleStruct, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeTupleVariant, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_map(self, _len: Option<usize>) -> Result<Self::SerializeMap, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStruct, Error> {
+        Err(key_must_be_a_string())
+    }
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _variant_index: u32,
+        _variant: &'static str,
+        _len: usize,
+    ) -> Result<Self::SerializeStructVariant, Error> {
+        Err(key_must_be_a_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::de::from_value;
+    use crate::eval_state::gc_register_my_thread;
+    use nix_bindings_store::store::Store;
+    use serde::{Deserialize, Serialize};
+    use std::collections::{BTreeMap, HashMap};
+
+    /// Serializes `value` and checks it against a Nix expression with `==`.
+    fn check<T: Serialize + ?Sized>(value: &T, expected: &str) {
+        let guard = gc_register_my_thread().unwrap();
+        let store = Store::open(None, HashMap::new()).unwrap();
+        let mut es = EvalState::new(store, []).unwrap();
+        let v = to_value(&mut es, value).unwrap();
+        let f = es
+            .eval_from_string(&format!("x: x == ({})", expected), "<test>")
+            .unwrap();
+        let r = es.call(f, v).unwrap();
+        assert!(es.require_bool(&r).unwrap(), "expected {}", expected);
+        drop(guard);
+    }
+
+    fn to_value_err<T: Serialize + ?Sized>(value: &T) -> Error {
+        let guard = gc_register_my_thread().unwrap();
+        let store = Store::open(None, HashMap::new()).unwrap();
+        let mut es = EvalState::new(store, []).unwrap();
+        let r = to_value(&mut es, value);
+        drop(guard);
+        match r {
+            Ok(_) => panic!("expected an error"),
+            Err(e) => e,
+        }
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    struct Nginx {
+        enable: bool,
+        port: u16,
+        root: Option<String>,
+        #[serde(rename = "extraConfig")]
+        extra_config: Vec<String>,
+        ratio: f64,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq, Serialize)]
+    enum Protocol {
+        Http,
+        Https { cert: String },
+        Port(u16),
+        Pair(u8, u8),
+    }
+
+    #[test]
+    fn ser_primitives() {
+        check(&true, "true");
+        check(&-3i8, "-3");
+        check(&u32::MAX, "4294967295");
+        check(&i64::MIN, "-9223372036854775807 - 1");
+        check(&1.5f32, "1.5");
+        check(&'x', "\"x\"");
+        check("hello", "\"hello\"");
+        check(&(), "null");
+        check(&None::<i64>, "null");
+        check(&Some(1), "1");
+    }
+
+    #[test]
+    fn ser_struct() {
+        check(
+            &Nginx {
+                enable: true,
+                port: 80,
+                root: None,
+                extra_config: vec!["a".into(), "b".into()],
+                ratio: 0.5,
+            },
+            r#"{ enable = true; port = 80; root = null; extraConfig = [ "a" "b" ]; ratio = 0.5; }"#,
+        );
+    }
+
+    #[test]
+    fn ser_seq() {
+        check(&Vec::<i64>::new(), "[ ]");
+        check(&(1, "a", [true]), r#"[ 1 "a" [ true ] ]"#);
+        check(&Bytes(&[1, 2]), "[ 1 2 ]");
+    }
+
+    /// Serializes as bytes rather than as a sequence of integers.
+    struct Bytes(&'static [u8]);
+    impl Serialize for Bytes {
+        fn serialize<S: ser::Serializer>(&self, s: S) -> Result<S::Ok, S::Error> {
+            s.serialize_bytes(self.0)
+        }
+    }
+
+    #[test]
+    fn ser_map() {
+        check(
+            &BTreeMap::from([("a b", 1), ("c", 2)]),
+            r#"{ "a b" = 1; c = 2; }"#,
+        );
+        check(
+            &BTreeMap::from([(1, "x"), (2, "y")]),
+            r#"{ "1" = "x"; "2" = "y"; }"#,
+        );
+    }
+
+    #[test]
+    fn ser_enum() {
+        check(
+            &[
+                Protocol::Http,
+                Protocol::Https {
+                    cert: "x.pem".into(),
+                },
+                Protocol::Port(8080),
+                Protocol::Pair(1, 2),
+            ],
+            r#"[ "Http" { Https.cert = "x.pem"; } { Port = 8080; } { Pair = [ 1 2 ]; } ]"#,
+        );
+    }
+
+    #[test]
+    fn ser_err_int_overflow() {
+        let e = to_value_err(&u64::MAX);
+        assert_eq!(
+            e.to_string(),
+            "integer 18446744073709551615 does not fit in a Nix integer"
+        );
+    }
+
+    #[test]
+    fn ser_err_path() {
+        let e = to_value_err(&BTreeMap::from([("a", vec![1u64, u64::MAX])]));
+        assert_eq!(e.path(), Some("a[1]"));
+        assert_eq!(
+            e.to_string(),
+            "a[1]: integer 18446744073709551615 does not fit in a Nix integer"
+        );
+
+        let e = to_value_err(&[Protocol::Https {
+            cert: "a\0b".to_owned(),
+        }]);
+        assert_eq!(e.path(), Some("[0].Https.cert"));
+    }
+
+    #[test]
+    fn ser_err_map_key() {
+        let e = to_value_err(&HashMap::from([((1, 2), 3)]));
+        assert_eq!(e.to_string(), "attribute names must be strings");
+    }
+
+    #[test]
+    fn ser_err_null_byte() {
+        let e = to_value_err("a\0b");
+        assert!(e.to_string().contains("null byte"), "{}", e);
+    }
+
+    #[test]
+    fn ser_round_trip() {
+        let guard = gc_register_my_thread().unwrap();
+        let store = Store::open(None, HashMap::new()).unwrap();
+        let mut es = EvalState::new(store, []).unwrap();
+        let nginx = Nginx {
+            enable: false,
+            port: 443,
+            root: Some("/var/www".into()),
+            extra_config: vec![],
+            ratio: -0.25,
+        };
+        let v = to_value(&mut es, &nginx).unwrap();
+        let back: Nginx = from_value(&mut es, &v).unwrap();
+        assert_eq!(back, nginx);
+
+        let protocols = vec![
+            Protocol::Http,
+            Protocol::Https { cert: "c".into() },
+            Protocol::Port(1),
+            Protocol::Pair(3, 4),
+        ];
+        let v = to_value(&mut es, &protocols).unwrap();
+        let back: Vec<Protocol> = from_value(&mut es, &v).unwrap();
+        assert_eq!(back, protocols);
+        drop(guard);
+    }
+}
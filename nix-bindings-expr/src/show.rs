@@ -0,0 +1,429 @@
+//! # Printing values
+//!
+//! [`EvalState::show`] renders a [`Value`] in Nix syntax, the way `nix eval` and `nix repl` print values.
+//! This is meant for diagnostics; use [`builtins.toJSON`](https://nix.dev/manual/nix/latest/language/builtins.html#builtins-toJSON) or the `serde` feature for data exchange.
+
+use crate::eval_state::EvalState;
+use crate::value::{Value, ValueType};
+use anyhow::Result;
+use nix_bindings_expr_sys as raw;
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+/// Options for [`EvalState::show`].
+#[derive(Clone, Debug)]
+pub struct ShowOptions {
+    /// The maximum nesting depth to print. Deeper lists and attribute sets are printed as `[ ... ]` and `{ ... }`.
+    ///
+    /// `None` prints the whole value.
+    pub max_depth: Option<usize>,
+
+    /// Whether to force [thunks](https://nix.dev/manual/nix/latest/language/evaluation.html#laziness).
+    ///
+    /// If `false`, unevaluated values are printed as `«thunk»` and are not evaluated.
+    /// Before Nix 2.33, the elements of lists and attribute sets are always forced, because the C API has no way to access them lazily.
+    pub force: bool,
+}
+impl Default for ShowOptions {
+    fn default() -> Self {
+        ShowOptions {
+            max_depth: None,
+            force: true,
+        }
+    }
+}
+
+impl EvalState {
+    /// Renders a value in Nix syntax, like `nix eval` and `nix repl`.
+    ///
+    /// Attribute sets are printed with their attributes sorted by name.
+    /// Functions are printed as `«lambda»`, and lists or attribute sets that were already printed are printed as `«repeated»`,
+    /// which also makes it possible to print cyclic values.
+    /// Repetition is detected by value identity, so a value may be printed once more than in `nix repl` before it's marked as repeated.
+    /// A value that was cut off by [`max_depth`][ShowOptions::max_depth] is printed again where it is reached with more depth left.
+    ///
+    /// Returns an [`Err`] if a value could not be evaluated.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use nix_bindings_expr::eval_state::{EvalState, test_init, gc_register_my_thread};
+    /// # use nix_bindings_expr::show::ShowOptions;
+    /// # use nix_bindings_store::store::Store;
+    /// # use std::collections::HashMap;
+    /// # fn example() -> anyhow::Result<()> {
+    /// # test_init();
+    /// # let guard = gc_register_my_thread()?;
+    /// let store = Store::open(None, HashMap::new())?;
+    /// let mut es = EvalState::new(store, [])?;
+    ///
+    /// let v = es.eval_from_string(r#"{ b = [ 1 "two" ]; a.c = null; }"#, "<example>")?;
+    /// assert_eq!(
+    ///     es.show(&v, &ShowOptions::default())?,
+    ///     r#"{ a = { c = null; }; b = [ 1 "two" ]; }"#
+    /// );
+    ///
+    /// let opts = ShowOptions { max_depth: Some(1), ..Default::default() };
+    /// assert_eq!(es.show(&v, &opts)?, r#"{ a = { ... }; b = [ ... ]; }"#);
+    /// # drop(guard);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[doc(alias = "print")]
+    #[doc(alias = "pretty_print")]
+    #[doc(alias = "display")]
+    pub fn show(&mut self, value: &Value, opts: &ShowOptions) -> Result<String> {
+        let mut printer = Printer {
+            eval_state: self,
+            opts,
+            seen: HashMap::new(),
+            out: String::new(),
+        };
+        printer.print(value, 0)?;
+        Ok(printer.out)
+    }
+}
+
+struct Printer<'a> {
+    eval_state: &'a mut EvalState,
+    opts: &'a ShowOptions,
+    /// The largest remaining depth that each list or attribute set was printed with
+    seen: HashMap<*mut raw::Value, usize>,
+    out: String,
+}
+
+/// How to print a list or attribute set
+enum Visit {
+    Repeated,
+    Truncated,
+    Print,
+}
+impl Printer<'_> {
+    fn print(&mut self, v: &Value, depth: usize) -> Result<()> {
+        let t = if self.opts.force {
+            self.eval_state.value_type(v)?
+        } else {
+            match self.eval_state.value_type_unforced(v) {
+                Some(t) => t,
+                None => {
+                    self.out.push_str("«thunk»");
+                    return Ok(());
+                }
+            }
+        };
+        match t {
+            ValueType::Int => {
+                let i = self.eval_state.require_int(v)?;
+                write!(self.out, "{}", i)?;
+            }
+            ValueType::Float => {
+                let f = self.eval_state.require_float(v)?;
+                self.out.push_str(&show_float(f));
+            }
+            ValueType::Bool => {
+                let b = self.eval_state.require_bool(v)?;
+                self.out.push_str(if b { "true" } else { "false" });
+            }
+            ValueType::Null => self.out.push_str("null"),
+            ValueType::String => {
+                let s = self.eval_state.require_string(v)?;
+                push_string_literal(&mut self.out, &s);
+            }
+            ValueType::Path => {
+                let p = self.eval_state.require_path(v)?;
+                self.out.push_str(&p);
+            }
+            ValueType::AttrSet => match self.visit(v, depth) {
+                Visit::Repeated => self.out.push_str("«repeated»"),
+                Visit::Truncated => self.out.push_str("{ ... }"),
+                Visit::Print => {
+                    let mut attrs = self.attrs(v)?;
+                    attrs.sort_by(|a, b| a.0.cmp(&b.0));
+                    self.out.push('{');
+                    for (name, value) in attrs {
+                        self.out.push(' ');
+                        push_attr_name(&mut self.out, &name);
+                        self.out.push_str(" = ");
+                        self.print(&value, depth + 1)?;
+                        self.out.push(';');
+                    }
+                    self.out.push_str(" }");
+                }
+            },
+            ValueType::List => match self.visit(v, depth) {
+                Visit::Repeated => self.out.push_str("«repeated»"),
+                Visit::Truncated => self.out.push_str("[ ... ]"),
+                Visit::Print => {
+                    let items = self.list(v)?;
+                    self.out.push('[');
+                    for value in items {
+                        self.out.push(' ');
+                        self.print(&value, depth + 1)?;
+                    }
+                    self.out.push_str(" ]");
+                }
+            },
+            ValueType::Function => self.out.push_str("«lambda»"),
+            ValueType::External => self.out.push_str("«external»"),
+            ValueType::Unknown => self.out.push_str("«unknown»"),
+        }
+        Ok(())
+    }
+
+    /// Decides how to print a list or attribute set at `depth`, and records it as printed.
+    ///
+    /// A value that was printed before is printed again if it now has more depth left, so that a shared value is not cut off just because it was first reached near `max_depth`.
+    fn visit(&mut self, v: &Value, depth: usize) -> Visit {
+        let remaining = self
+            .opts
+            .max_depth
+            .map_or(usize::MAX, |max| max.saturating_sub(depth));
+        let ptr = unsafe { v.raw_ptr() };
+        if self.seen.get(&ptr).is_some_and(|&r| r >= remaining) {
+            Visit::Repeated
+        } else if remaining == 0 {
+            Visit::Truncated
+        } else {
+            self.seen.insert(ptr, remaining);
+            Visit::Print
+        }
+    }
+
+    fn attrs(&mut self, v: &Value) -> Result<Vec<(String, Value)>> {
+        #[cfg(nix_at_least = "2.33")]
+        if !self.opts.force {
+            return self.eval_state.attrs_iter_lazy(v)?.collect();
+        }
+        self.eval_state.attrs_iter_strict(v)?.collect()
+    }
+
+    fn list(&mut self, v: &Value) -> Result<Vec<Value>> {
+        #[cfg(nix_at_least = "2.33")]
+        if !self.opts.force {
+            let es = &mut *self.eval_state;
+            let size = es.require_list_size(v)?;
+            let state = unsafe { es.raw_ptr() };
+            return (0..size)
+                .map(|i| {
+                    let element = unsafe {
+                        nix_bindings_util::check_call!(raw::get_list_byidx_lazy(
+                            &mut es.context,
+                            v.raw_ptr(),
+                            state,
+                            i
+                        ))
+                    }?;
+                    Ok(unsafe { Value::new(element) })
+                })
+                .collect();
+        }
+        self.eval_state.require_list_strict(v)
+    }
+}
+
+/// Formats a float like Nix does, which uses the C++ stream defaults (like `%g`).
+fn show_float(f: f64) -> String {
+    if f.is_nan() {
+        return if f.is_sign_negative() { "-nan" } else { "nan" }.to_owned();
+    }
+    if f.is_infinite() {
+        return if f < 0.0 { "-inf" } else { "inf" }.to_owned();
+    }
+    if f == 0.0 {
+        return if f.is_sign_negative() { "-0" } else { "0" }.to_owned();
+    }
+    const PRECISION: i32 = 6;
+    // The exponent after rounding to PRECISION significant digits
+    let sci = format!("{:.*e}", (PRECISION - 1) as usize, f);
+    let (mantissa, exp) = sci.split_once('e').unwrap();
+    let exp: i32 = exp.parse().unwrap();
+    if !(-4..PRECISION).contains(&exp) {
+        format!(
+            "{}e{}{:02}",
+            trim_fraction(mantissa),
+            if exp < 0 { '-' } else { '+' },
+            exp.abs()
+        )
+    } else {
+        let fixed = format!("{:.*}", (PRECISION - 1 - exp) as usize, f);
+        trim_fraction(&fixed).to_owned()
+    }
+}
+
+fn trim_fraction(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
+
+fn push_string_literal(out: &mut String, s: &str) {
+    out.push('"');
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '$' if chars.peek() == Some(&'{') => out.push_str("\\$"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn push_attr_name(out: &mut String, name: &str) {
+    if is_identifier(name) {
+        out.push_str(name);
+    } else {
+        push_string_literal(out, name);
+    }
+}
+
+/// Whether `s` can be written as an attribute name without quotes, i.e. whether it is an identifier and not a keyword.
+pub(crate) fn is_identifier(s: &str) -> bool {
+    const KEYWORDS: [&str; 9] = [
+        "assert", "else", "if", "in", "inherit", "let", "rec", "then", "with",
+    ];
+    let mut chars = s.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '\'' || c == '-')
+        && !KEYWORDS.contains(&s)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval_state::gc_register_my_thread;
+    use nix_bindings_store::store::Store;
+    use std::collections::HashMap;
+
+    fn show(expr: &str, opts: &ShowOptions) -> String {
+        let guard = gc_register_my_thread().unwrap();
+        let store = Store::open(None, HashMap::new()).unwrap();
+        let mut es = EvalState::new(store, []).unwrap();
+        let v = es.eval_from_string(expr, "<test>").unwrap();
+        let s = es.show(&v, opts).unwrap();
+        drop(guard);
+        s
+    }
+
+    #[test]
+    fn show_simple() {
+        let opts = ShowOptions::default();
+        assert_eq!(show("1", &opts), "1");
+        assert_eq!(show("-1", &opts), "-1");
+        assert_eq!(show("true", &opts), "true");
+        assert_eq!(show("null", &opts), "null");
+        assert_eq!(show("/foo/bar", &opts), "/foo/bar");
+        assert_eq!(show("x: x", &opts), "«lambda»");
+        assert_eq!(show("[ ]", &opts), "[ ]");
+        assert_eq!(show("{ }", &opts), "{ }");
+    }
+
+    #[test]
+    fn show_string_escapes() {
+        let opts = ShowOptions::default();
+        assert_eq!(
+            show(r#""a\"b\\c\nd\te$x\${y}""#, &opts),
+            r#""a\"b\\c\nd\te$x\${y}""#
+        );
+    }
+
+    #[test]
+    fn show_float() {
+        let opts = ShowOptions::default();
+        assert_eq!(show("1.5", &opts), "1.5");
+        assert_eq!(show("3.0", &opts), "3");
+        assert_eq!(show("1.0 / 3", &opts), "0.333333");
+        assert_eq!(show("1.0e10", &opts), "1e+10");
+        assert_eq!(show("0.0001", &opts), "0.0001");
+        assert_eq!(show("0.00001", &opts), "1e-05");
+        assert_eq!(show("123456.7", &opts), "123457");
+    }
+
+    #[test]
+    fn show_nested() {
+        let opts = ShowOptions::default();
+        assert_eq!(
+            show(r#"{ z = [ 1 { y = "a"; } ]; "a b" = 2; "if" = 3; }"#, &opts),
+            r#"{ "a b" = 2; "if" = 3; z = [ 1 { y = "a"; } ]; }"#
+        );
+    }
+
+    #[test]
+    fn show_max_depth() {
+        let opts = ShowOptions {
+            max_depth: Some(1),
+            ..Default::default()
+        };
+        assert_eq!(
+            show("{ a = { b = 1; }; c = [ 1 ]; d = 2; }", &opts),
+            "{ a = { ... }; c = [ ... ]; d = 2; }"
+        );
+        let opts = ShowOptions {
+            max_depth: Some(0),
+            ..Default::default()
+        };
+        assert_eq!(show("[ 1 ]", &opts), "[ ... ]");
+    }
+
+    #[test]
+    fn show_repeated() {
+        let opts = ShowOptions::default();
+        assert_eq!(
+            show("let x = { inherit x; y = 1; }; in x", &opts),
+            // The root is a copy of `x`, so it's printed once more than `nix repl` would
+            "{ x = { x = «repeated»; y = 1; }; y = 1; }"
+        );
+    }
+
+    #[test]
+    fn show_repeated_max_depth() {
+        let opts = ShowOptions {
+            max_depth: Some(2),
+            ..Default::default()
+        };
+        // `s` is cut off under `a`, but has room to be printed under `c`
+        assert_eq!(
+            show("let s = { t = 1; }; in { a = { b = s; }; c = s; }", &opts),
+            "{ a = { b = { ... }; }; c = { t = 1; }; }"
+        );
+        assert_eq!(
+            show("let s = { t = 1; }; in { a = s; c = { b = s; }; }", &opts),
+            "{ a = { t = 1; }; c = { b = «repeated»; }; }"
+        );
+    }
+
+    #[test]
+    fn show_error() {
+        let guard = gc_register_my_thread().unwrap();
+        let store = Store::open(None, HashMap::new()).unwrap();
+        let mut es = EvalState::new(store, []).unwrap();
+        let v = es
+            .eval_from_string(r#"{ a = throw "oh no"; }"#, "<test>")
+            .unwrap();
+        let r = es.show(&v, &ShowOptions::default());
+        assert!(r.unwrap_err().to_string().contains("oh no"));
+        drop(guard);
+    }
+
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn show_unforced() {
+        let opts = ShowOptions {
+            force: false,
+            ..Default::default()
+        };
+        assert_eq!(
+            show(r#"{ a = throw "oh no"; b = [ (1 + 1) 3 ]; }"#, &opts),
+            "{ a = «thunk»; b = «thunk»; }"
+        );
+        assert_eq!(show(r#"{ b = [ (1 + 1) 3 ]; }.b"#, &opts), "[ «thunk» 3 ]");
+    }
+}
@@ -271,7 +271,7 @@ mod tests {
         // concurrent Nix operations might read the setting while it's being modified
         INIT.call_once(|| {
             nix_bindings_expr::eval_state::init().unwrap();
-            nix_bindings_util::settings::set("experimental-features", "flakes").unwrap();
+            nix_bindings_util::settings::set_experimental_features(["flakes"]).unwrap();
         });
     }
 
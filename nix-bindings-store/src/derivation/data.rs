@@ -0,0 +1,689 @@
+use anyhow::{bail, Context as _, Result};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[cfg(nix_at_least = "2.33")]
+use data_encoding::{BASE64, HEXLOWER};
+#[cfg(nix_at_least = "2.33")]
+use ring::digest;
+
+use super::Derivation;
+#[cfg(nix_at_least = "2.33")]
+use crate::path::{nix32_encode, STORE_PATH_HASH_SIZE};
+use crate::store::Store;
+
+/// How the contents of a content-addressed output are hashed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ContentAddressMethod {
+    /// The NAR serialization of the file system object.
+    Nar,
+    /// The contents of a single regular file.
+    Flat,
+    /// Like [`Flat`][ContentAddressMethod::Flat], but the file may refer to other store paths. Used for `.drv` files and `builtins.toFile`.
+    Text,
+    /// The git tree or blob hash.
+    Git,
+}
+
+impl ContentAddressMethod {
+    /// The name of the method, as in the derivation JSON format.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ContentAddressMethod::Nar => "nar",
+            ContentAddressMethod::Flat => "flat",
+            ContentAddressMethod::Text => "text",
+            ContentAddressMethod::Git => "git",
+        }
+    }
+
+    fn parse(s: &str) -> Result<Self> {
+        match s {
+            "nar" => Ok(ContentAddressMethod::Nar),
+            "flat" => Ok(ContentAddressMethod::Flat),
+            "text" => Ok(ContentAddressMethod::Text),
+            "git" => Ok(ContentAddressMethod::Git),
+            _ => bail!("unknown content address method `{}`", s),
+        }
+    }
+}
+
+/// An output of a derivation, and how its store path is determined.
+///
+/// Store paths are written without the store directory, as in the derivation JSON format, e.g. `8bs8sd27bzzy6w94fznjd2j8ldmdg7x6-myname`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DerivationOutput {
+    /// The store path is computed from the derivation and its inputs.
+    InputAddressed { path: String },
+    /// A fixed-output derivation: the hash of the contents is known in advance.
+    CaFixed {
+        method: ContentAddressMethod,
+        /// The expected hash, e.g. `sha256-...`.
+        hash: String,
+    },
+    /// The store path is computed from the contents after the build.
+    CaFloating {
+        method: ContentAddressMethod,
+        /// The hash algorithm, e.g. `sha256`.
+        hash_algo: String,
+    },
+    /// Input-addressed, but the store path is not known yet, because some inputs are content-addressed and have not been built.
+    Deferred,
+    /// Built every time it is needed, without caching. Requires the `impure-derivations` experimental feature.
+    Impure {
+        method: ContentAddressMethod,
+        /// The hash algorithm, e.g. `sha256`.
+        hash_algo: String,
+    },
+}
+
+impl DerivationOutput {
+    fn from_json(v: &Value) -> Result<Self> {
+        let obj = v.as_object().context("expected an object")?;
+        if let Some(path) = obj.get("path") {
+            return Ok(DerivationOutput::InputAddressed {
+                path: as_string(path, "path")?,
+            });
+        }
+        let Some(method) = obj.get("method") else {
+            return Ok(DerivationOutput::Deferred);
+        };
+        let method = ContentAddressMethod::parse(&as_string(method, "method")?)?;
+        if let Some(hash) = obj.get("hash") {
+            return Ok(DerivationOutput::CaFixed {
+                method,
+                hash: as_string(hash, "hash")?,
+            });
+        }
+        let hash_algo = as_string(field(obj, "hashAlgo")?, "hashAlgo")?;
+        if obj.get("impure") == Some(&Value::Bool(true)) {
+            Ok(DerivationOutput::Impure { method, hash_algo })
+        } else {
+            Ok(DerivationOutput::CaFloating { method, hash_algo })
+        }
+    }
+
+    /// The store path of the output, without the store directory, if it is known before building.
+    ///
+    /// That is the path of an input-addressed output, or the path of a fixed output, which follows from its expected hash, like `makeFixedOutputPath` in Nix.
+    #[cfg(nix_at_least = "2.33")]
+    pub(crate) fn path(
+        &self,
+        store_dir: &str,
+        drv_name: &str,
+        output_name: &str,
+    ) -> Result<Option<String>> {
+        let name = if output_name == "out" {
+            drv_name.to_owned()
+        } else {
+            format!("{drv_name}-{output_name}")
+        };
+        match self {
+            DerivationOutput::InputAddressed { path } => Ok(Some(path.clone())),
+            DerivationOutput::CaFixed { method, hash } => {
+                let (algo, digest) = parse_sri_hash(hash)?;
+                let hash = format!("{algo}:{}", HEXLOWER.encode(&digest));
+                let path = match (method, algo) {
+                    (ContentAddressMethod::Nar, "sha256") => {
+                        make_store_path("source", &hash, store_dir, &name)
+                    }
+                    (ContentAddressMethod::Text, "sha256") => {
+                        make_store_path("text", &hash, store_dir, &name)
+                    }
+                    (ContentAddressMethod::Text, _) => {
+                        bail!("text hashing requires sha256, but got `{algo}`")
+                    }
+                    (ContentAddressMethod::Git, "md5" | "sha512") => {
+                        bail!("git hashing requires sha1 or sha256, but got `{algo}`")
+                    }
+                    (
+                        ContentAddressMethod::Nar
+                        | ContentAddressMethod::Flat
+                        | ContentAddressMethod::Git,
+                        _,
+                    ) => {
+                        let prefix = match method {
+                            ContentAddressMethod::Nar => "r:",
+                            ContentAddressMethod::Git => "git:",
+                            _ => "",
+                        };
+                        let inner = digest::digest(
+                            &digest::SHA256,
+                            format!("fixed:out:{prefix}{hash}:").as_bytes(),
+                        );
+                        let inner = format!("sha256:{}", HEXLOWER.encode(inner.as_ref()));
+                        make_store_path("output:out", &inner, store_dir, &name)
+                    }
+                };
+                Ok(Some(path))
+            }
+            DerivationOutput::CaFloating { .. }
+            | DerivationOutput::Deferred
+            | DerivationOutput::Impure { .. } => Ok(None),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        match self {
+            DerivationOutput::InputAddressed { path } => json!({ "path": path }),
+            DerivationOutput::CaFixed { method, hash } => {
+                json!({ "method": method.as_str(), "hash": hash })
+            }
+            DerivationOutput::CaFloating { method, hash_algo } => {
+                json!({ "method": method.as_str(), "hashAlgo": hash_algo })
+            }
+            DerivationOutput::Deferred => json!({}),
+            DerivationOutput::Impure { method, hash_algo } => {
+                json!({ "method": method.as_str(), "hashAlgo": hash_algo, "impure": true })
+            }
+        }
+    }
+}
+
+/// The contents of a [`Derivation`], as plain Rust data.
+///
+/// Obtain it with [`Derivation::to_data`], or construct one with a [`DerivationBuilder`].
+///
+/// Store paths are written without the store directory, as in the [derivation JSON format](https://nix.dev/manual/nix/latest/protocols/json/derivation.html).
+///
+/// Requires the `derivation-data` feature.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DerivationData {
+    pub name: String,
+    /// The platform to build on, e.g. `x86_64-linux`.
+    pub system: String,
+    /// The program to run.
+    pub builder: String,
+    /// The arguments of the builder.
+    pub args: Vec<String>,
+    /// The environment variables of the builder.
+    pub env: BTreeMap<String, String>,
+    /// The derivations whose outputs are inputs to this one, with the names of the outputs that are used.
+    pub input_drvs: BTreeMap<String, BTreeSet<String>>,
+    /// Store paths that are inputs to this derivation, and are not derivation outputs.
+    pub input_srcs: BTreeSet<String>,
+    /// The outputs, by name.
+    pub outputs: BTreeMap<String, DerivationOutput>,
+    /// The structured attributes (`__structuredAttrs = true`), if enabled.
+    pub structured_attrs: Option<Map<String, Value>>,
+}
+
+impl DerivationData {
+    /// Parse the [derivation JSON format](https://nix.dev/manual/nix/latest/protocols/json/derivation.html), as produced by [`Derivation::to_json_string`].
+    pub fn from_json(v: &Value) -> Result<Self> {
+        let obj = v
+            .as_object()
+            .context("derivation JSON: expected an object")?;
+        Self::from_json_object(obj).context("derivation JSON")
+    }
+
+    fn from_json_object(obj: &Map<String, Value>) -> Result<Self> {
+        let inputs = as_object(field(obj, "inputs")?, "inputs")?;
+        let input_drvs = as_object(field(inputs, "drvs")?, "inputs.drvs")?
+            .iter()
+            .map(|(path, v)| {
+                let v = as_object(v, path)?;
+                if let Some(dynamic) = v.get("dynamicOutputs") {
+                    if !as_object(dynamic, "dynamicOutputs")?.is_empty() {
+                        bail!("dynamic derivation outputs of `{}` are not supported", path);
+                    }
+                }
+                let outputs = as_array(field(v, "outputs")?, "outputs")?
+                    .iter()
+                    .map(|o| as_string(o, "outputs"))
+                    .collect::<Result<_>>()?;
+                Ok((path.clone(), outputs))
+            })
+            .collect::<Result<_>>()?;
+        let input_srcs = as_array(field(inputs, "srcs")?, "inputs.srcs")?
+            .iter()
+            .map(|p| as_string(p, "inputs.srcs"))
+            .collect::<Result<_>>()?;
+        let outputs = as_object(field(obj, "outputs")?, "outputs")?
+            .iter()
+            .map(|(name, v)| {
+                let output =
+                    DerivationOutput::from_json(v).with_context(|| format!("output `{}`", name))?;
+                Ok((name.clone(), output))
+            })
+            .collect::<Result<_>>()?;
+        Ok(DerivationData {
+            name: as_string(field(obj, "name")?, "name")?,
+            system: as_string(field(obj, "system")?, "system")?,
+            builder: as_string(field(obj, "builder")?, "builder")?,
+            args: as_array(field(obj, "args")?, "args")?
+                .iter()
+                .map(|a| as_string(a, "args"))
+                .collect::<Result<_>>()?,
+            env: as_object(field(obj, "env")?, "env")?
+                .iter()
+                .map(|(k, v)| Ok((k.clone(), as_string(v, k)?)))
+                .collect::<Result<_>>()?,
+            input_drvs,
+            input_srcs,
+            outputs,
+            structured_attrs: match obj.get("structuredAttrs") {
+                None | Some(Value::Null) => None,
+                Some(v) => Some(as_object(v, "structuredAttrs")?.clone()),
+            },
+        })
+    }
+
+    /// Render the [derivation JSON format](https://nix.dev/manual/nix/latest/protocols/json/derivation.html), as accepted by [`Store::derivation_from_json`].
+    pub fn to_json(&self) -> Value {
+        let input_drvs: Map<String, Value> = self
+            .input_drvs
+            .iter()
+            .map(|(path, outputs)| {
+                (
+                    path.clone(),
+                    json!({ "outputs": outputs, "dynamicOutputs": {} }),
+                )
+            })
+            .collect();
+        let outputs: Map<String, Value> = self
+            .outputs
+            .iter()
+            .map(|(name, output)| (name.clone(), output.to_json()))
+            .collect();
+        let mut v = json!({
+            "version": 4,
+            "name": self.name,
+            "system": self.system,
+            "builder": self.builder,
+            "args": self.args,
+            "env": self.env,
+            "inputs": {
+                "drvs": input_drvs,
+                "srcs": self.input_srcs,
+            },
+            "outputs": outputs,
+        });
+        if let Some(structured_attrs) = &self.structured_attrs {
+            v["structuredAttrs"] = Value::Object(structured_attrs.clone());
+        }
+        v
+    }
+}
+
+/// Split an SRI hash, e.g. `sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=`, into its algorithm and digest.
+#[cfg(nix_at_least = "2.33")]
+fn parse_sri_hash(hash: &str) -> Result<(&str, Vec<u8>)> {
+    let (algo, base64) = hash
+        .split_once('-')
+        .with_context(|| format!("expected an SRI hash, but got `{hash}`"))?;
+    let expected_len = match algo {
+        "md5" => 16,
+        "sha1" => 20,
+        "sha256" => 32,
+        "sha512" => 64,
+        _ => bail!("unknown hash algorithm `{algo}`"),
+    };
+    let digest = BASE64
+        .decode(base64.as_bytes())
+        .with_context(|| format!("invalid base64 in hash `{hash}`"))?;
+    if digest.len() != expected_len {
+        bail!("wrong length for a {algo} hash: `{hash}`");
+    }
+    Ok((algo, digest))
+}
+
+/// Like `makeStorePath` in Nix: the base name of the store path for a fingerprint of `type` and `hash`, e.g. `sha256:<base16>`.
+#[cfg(nix_at_least = "2.33")]
+fn make_store_path(r#type: &str, hash: &str, store_dir: &str, name: &str) -> String {
+    let fingerprint = format!("{}:{hash}:{store_dir}:{name}", r#type);
+    let digest = digest::digest(&digest::SHA256, fingerprint.as_bytes());
+    let mut compressed = [0u8; STORE_PATH_HASH_SIZE];
+    for (i, b) in digest.as_ref().iter().enumerate() {
+        compressed[i % STORE_PATH_HASH_SIZE] ^= b;
+    }
+    format!("{}-{name}", nix32_encode(&compressed))
+}
+
+fn field<'a>(obj: &'a Map<String, Value>, name: &str) -> Result<&'a Value> {
+    obj.get(name)
+        .with_context(|| format!("missing field `{}`", name))
+}
+fn as_string(v: &Value, what: &str) -> Result<String> {
+    match v {
+        Value::String(s) => Ok(s.clone()),
+        _ => bail!("expected a string for `{}`, but got {}", what, v),
+    }
+}
+fn as_array<'a>(v: &'a Value, what: &str) -> Result<&'a Vec<Value>> {
+    v.as_array()
+        .with_context(|| format!("expected an array for `{}`, but got {}", what, v))
+}
+fn as_object<'a>(v: &'a Value, what: &str) -> Result<&'a Map<String, Value>> {
+    v.as_object()
+        .with_context(|| format!("expected an object for `{}`, but got {}", what, v))
+}
+
+/// Constructs a [`Derivation`] without writing JSON by hand.
+///
+/// Requires the `derivation-data` feature.
+///
+/// Like the `derivation` function in the Nix language, [`new`][DerivationBuilder::new] sets the `name`, `system` and `builder` environment variables.
+/// Unlike it, environment variables for the outputs are not added, because their values can not be computed here;
+/// for content-addressed outputs, use [`DerivationBuilder::env`] to set them to their placeholders (see `builtins.placeholder`).
+///
+/// # Examples
+///
+/// ```no_run
+/// # use nix_bindings_store::derivation::{ContentAddressMethod, DerivationBuilder, DerivationOutput};
+/// # use nix_bindings_store::store::Store;
+/// # fn example(store: &mut Store) -> anyhow::Result<()> {
+/// let drv = DerivationBuilder::new("hello", "x86_64-linux", "/bin/sh")
+///     .args(["-c", "echo hello > $out"])
+///     .output(
+///         "out",
+///         DerivationOutput::CaFloating {
+///             method: ContentAddressMethod::Nar,
+///             hash_algo: "sha256".to_owned(),
+///         },
+///     )
+///     .env("out", "/1rz4g4znpzjwh1xymhjpm42vipw92pr73vdgl6xs1hycac8kf2n9")
+///     .build(store)?;
+/// let drv_path = store.add_derivation(&drv)?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct DerivationBuilder {
+    data: DerivationData,
+}
+
+impl DerivationBuilder {
+    /// Creates a new [`DerivationBuilder`] without arguments, inputs and outputs.
+    pub fn new(
+        name: impl Into<String>,
+        system: impl Into<String>,
+        builder: impl Into<String>,
+    ) -> Self {
+        let name = name.into();
+        let system = system.into();
+        let builder = builder.into();
+        let env = BTreeMap::from([
+            ("name".to_owned(), name.clone()),
+            ("system".to_owned(), system.clone()),
+            ("builder".to_owned(), builder.clone()),
+        ]);
+        DerivationBuilder {
+            data: DerivationData {
+                name,
+                system,
+                builder,
+                args: Vec::new(),
+                env,
+                input_drvs: BTreeMap::new(),
+                input_srcs: BTreeSet::new(),
+                outputs: BTreeMap::new(),
+                structured_attrs: None,
+            },
+        }
+    }
+    /// Appends an argument for the builder.
+    pub fn arg(mut self, arg: impl Into<String>) -> Self {
+        self.data.args.push(arg.into());
+        self
+    }
+    /// Appends arguments for the builder.
+    pub fn args(mut self, args: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.data.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+    /// Sets an environment variable, replacing any previous value.
+    pub fn env(mut self, name: impl Into<String>, value: impl Into<String>) -> Self {
+        self.data.env.insert(name.into(), value.into());
+        self
+    }
+    /// Adds outputs of a derivation as inputs. `drv_path` is written without the store directory.
+    pub fn input_drv(
+        mut self,
+        drv_path: impl Into<String>,
+        outputs: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.data
+            .input_drvs
+            .entry(drv_path.into())
+            .or_default()
+            .extend(outputs.into_iter().map(Into::into));
+        self
+    }
+    /// Adds a store path as input. `path` is written without the store directory.
+    pub fn input_src(mut self, path: impl Into<String>) -> Self {
+        self.data.input_srcs.insert(path.into());
+        self
+    }
+    /// Adds an output, replacing any previous output with the same name.
+    pub fn output(mut self, name: impl Into<String>, output: DerivationOutput) -> Self {
+        self.data.outputs.insert(name.into(), output);
+        self
+    }
+    /// Enables structured attributes, with the given attributes.
+    pub fn structured_attrs(mut self, attrs: Map<String, Value>) -> Self {
+        self.data.structured_attrs = Some(attrs);
+        self
+    }
+    /// The derivation as plain data.
+    pub fn data(&self) -> &DerivationData {
+        &self.data
+    }
+    /// Builds the configured [`Derivation`]. This does not add it to the store; use [`Store::add_derivation`] for that.
+    pub fn build(&self, store: &mut Store) -> Result<Derivation> {
+        store.derivation_from_json(&self.data.to_json().to_string())
+    }
+}
+
+impl Derivation {
+    /// Get the contents of the derivation as plain Rust data.
+    ///
+    /// **Requires Nix 2.33 or later**, and the `derivation-data` feature.
+    #[cfg(nix_at_least = "2.33")]
+    pub fn to_data(&self) -> Result<DerivationData> {
+        let json: Value = serde_json::from_str(&self.to_json_string()?)
+            .context("Failed to parse derivation JSON")?;
+        DerivationData::from_json(&json)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn floating(builder: DerivationBuilder) -> DerivationBuilder {
+        builder
+            .output(
+                "out",
+                DerivationOutput::CaFloating {
+                    method: ContentAddressMethod::Nar,
+                    hash_algo: "sha256".to_owned(),
+                },
+            )
+            .env(
+                "out",
+                "/1rz4g4znpzjwh1xymhjpm42vipw92pr73vdgl6xs1hycac8kf2n9",
+            )
+    }
+
+    #[test]
+    fn builder_json() {
+        let data = floating(DerivationBuilder::new("myname", "x86_64-linux", "/bin/sh"))
+            .arg("-c")
+            .arg("echo $name foo > $out")
+            .data()
+            .clone();
+        assert_eq!(
+            data.to_json(),
+            json!({
+                "args": ["-c", "echo $name foo > $out"],
+                "builder": "/bin/sh",
+                "env": {
+                    "builder": "/bin/sh",
+                    "name": "myname",
+                    "out": "/1rz4g4znpzjwh1xymhjpm42vipw92pr73vdgl6xs1hycac8kf2n9",
+                    "system": "x86_64-linux"
+                },
+                "inputs": {
+                    "drvs": {},
+                    "srcs": []
+                },
+                "name": "myname",
+                "outputs": {
+                    "out": {
+                        "hashAlgo": "sha256",
+                        "method": "nar"
+                    }
+                },
+                "system": "x86_64-linux",
+                "version": 4
+            })
+        );
+    }
+
+    #[test]
+    fn json_round_trip() {
+        let data = DerivationBuilder::new("many", "x86_64-linux", "/bin/sh")
+            .input_drv("8bs8sd27bzzy6w94fznjd2j8ldmdg7x6-dep.drv", ["out", "dev"])
+            .input_src("rdd4pnr4x9rqc9wgbibhngv217w2xvxl-src")
+            .output(
+                "out",
+                DerivationOutput::InputAddressed {
+                    path: "0gkw1366qklqfqb2lw1pikgdqh3cmi3n-many".to_owned(),
+                },
+            )
+            .output(
+                "fixed",
+                DerivationOutput::CaFixed {
+                    method: ContentAddressMethod::Flat,
+                    hash: "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU=".to_owned(),
+                },
+            )
+            .output("deferred", DerivationOutput::Deferred)
+            .output(
+                "impure",
+                DerivationOutput::Impure {
+                    method: ContentAddressMethod::Git,
+                    hash_algo: "sha1".to_owned(),
+                },
+            )
+            .structured_attrs(Map::from_iter([("n".to_owned(), json!(1))]))
+            .data()
+            .clone();
+        assert_eq!(DerivationData::from_json(&data.to_json()).unwrap(), data);
+    }
+
+    #[test]
+    fn from_json_errors() {
+        let mut json = floating(DerivationBuilder::new("myname", "x86_64-linux", "/bin/sh"))
+            .data()
+            .to_json();
+        json["outputs"]["out"]["method"] = json!("bogus");
+        let e = DerivationData::from_json(&json).unwrap_err();
+        assert!(format!("{:#}", e).contains("unknown content address method `bogus`"));
+
+        json["outputs"]["out"]["method"] = json!("nar");
+        json.as_object_mut().unwrap().remove("builder");
+        let e = DerivationData::from_json(&json).unwrap_err();
+        assert!(format!("{:#}", e).contains("missing field `builder`"));
+    }
+
+    #[cfg(nix_at_least = "2.33")]
+    fn fixed_path(
+        method: ContentAddressMethod,
+        hash: &str,
+        drv_name: &str,
+        output_name: &str,
+    ) -> Result<Option<String>> {
+        DerivationOutput::CaFixed {
+            method,
+            hash: hash.to_owned(),
+        }
+        .path("/nix/store", drv_name, output_name)
+    }
+
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn fixed_output_path() {
+        // From the Nix test suite, src/libstore-tests/data/derivation/output-caFixed{Flat,NAR,Text}.json
+        let hash = "sha256-iUUXyRY8iW7DGirb0zwGgf1fRbLA7wimTJKgP7l/OQ8=";
+        let path = |method| {
+            fixed_path(method, hash, "drv-name", "output-name")
+                .unwrap()
+                .unwrap()
+        };
+        assert_eq!(
+            path(ContentAddressMethod::Flat),
+            "rhcg9h16sqvlbpsa6dqm57sbr2al6nzg-drv-name-output-name"
+        );
+        assert_eq!(
+            path(ContentAddressMethod::Nar),
+            "c015dhfh5l0lp6wxyvdn7bmwhbbr6hr9-drv-name-output-name"
+        );
+        assert_eq!(
+            path(ContentAddressMethod::Text),
+            "6s1zwabh956jvhv4w9xcdb5jiyanyxg1-drv-name-output-name"
+        );
+
+        // From the Nix manual
+        assert_eq!(
+            fixed_path(
+                ContentAddressMethod::Flat,
+                "sha256-MeBmE3qWJnbon2nRtlOC3pWn732RS4y5VvQepy4PUWs=",
+                "hello-2.10.tar.gz",
+                "out"
+            )
+            .unwrap()
+            .unwrap(),
+            "3x7dwzq014bblazs7kq20p9hyzz0qh8g-hello-2.10.tar.gz"
+        );
+    }
+
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn fixed_output_path_errors() {
+        let e = fixed_path(
+            ContentAddressMethod::Text,
+            "sha1-2jmj7l5rSw0yVb/vlWAYkK/YBwk=",
+            "x",
+            "out",
+        )
+        .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "text hashing requires sha256, but got `sha1`"
+        );
+        let e = fixed_path(
+            ContentAddressMethod::Git,
+            "md5-1B2M2Y8AsgTpgAmY7PhCfg==",
+            "x",
+            "out",
+        )
+        .unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "git hashing requires sha1 or sha256, but got `md5`"
+        );
+        let e = fixed_path(ContentAddressMethod::Flat, "sha256-AAAA", "x", "out").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "wrong length for a sha256 hash: `sha256-AAAA`"
+        );
+        let e = fixed_path(ContentAddressMethod::Flat, "blake3-AAAA", "x", "out").unwrap_err();
+        assert_eq!(e.to_string(), "unknown hash algorithm `blake3`");
+        let e = fixed_path(ContentAddressMethod::Flat, "47DEQpj8HBSa", "x", "out").unwrap_err();
+        assert_eq!(
+            e.to_string(),
+            "expected an SRI hash, but got `47DEQpj8HBSa`"
+        );
+    }
+
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn build_and_to_data() {
+        let mut store = Store::open(Some("dummy://"), []).unwrap();
+        let builder = floating(DerivationBuilder::new("myname", "x86_64-linux", "/bin/sh"))
+            .args(["-c", "echo $name foo > $out"]);
+        let drv = builder.build(&mut store).unwrap();
+        assert_eq!(&drv.to_data().unwrap(), builder.data());
+    }
+}
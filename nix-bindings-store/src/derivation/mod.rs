@@ -85,6 +85,11 @@ impl Drop for Derivation {
     }
 }
 
+#[cfg(feature = "derivation-data")]
+mod data;
+#[cfg(feature = "derivation-data")]
+pub use data::{ContentAddressMethod, DerivationBuilder, DerivationData, DerivationOutput};
+
 #[cfg(feature = "harmonia")]
 mod harmonia;
 
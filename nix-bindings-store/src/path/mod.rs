@@ -51,6 +51,14 @@ impl StorePath {
         Ok(result)
     }
 
+    /// Get the hash part of the store path as it appears in the path, e.g. `rdd4pnr4x9rqc9wgbibhngv217w2xvxl`.
+    ///
+    /// This is the nix32 encoding of [`hash`][StorePath::hash], as accepted by [`Store::query_path_from_hash_part`][crate::store::Store::query_path_from_hash_part].
+    #[cfg(nix_at_least = "2.33")]
+    pub fn hash_part(&self) -> Result<String> {
+        Ok(nix32_encode(&self.hash()?))
+    }
+
     /// Create a StorePath from hash and name components.
     #[cfg(nix_at_least = "2.33")]
     pub fn from_parts(hash: &[u8; STORE_PATH_HASH_SIZE], name: &str) -> Result<Self> {
@@ -117,6 +125,12 @@ impl Clone for StorePath {
     }
 }
 
+/// A `nix_store_path` owns a C++ `nix::StorePath`, which is a plain string value: it has no thread affinity,
+/// and it does not refer to the store or [`Context`][nix_bindings_util::context::Context] it was created with.
+/// `nix_store_path_free` only deletes that value, so a `StorePath` may be dropped on another thread,
+/// and outlive the [`Store`][crate::store::Store] it came from.
+unsafe impl Send for StorePath {}
+
 impl Drop for StorePath {
     fn drop(&mut self) {
         unsafe {
@@ -128,6 +142,25 @@ impl Drop for StorePath {
 #[cfg(all(feature = "harmonia", nix_at_least = "2.33"))]
 mod harmonia;
 
+/// Encode bytes in Nix's base-32 alphabet, least significant digit last.
+#[cfg(nix_at_least = "2.33")]
+pub(crate) fn nix32_encode(bytes: &[u8]) -> String {
+    const CHARS: &[u8] = b"0123456789abcdfghijklmnpqrsvwxyz";
+    let len = (bytes.len() * 8).div_ceil(5);
+    (0..len)
+        .rev()
+        .map(|n| {
+            let b = n * 5;
+            let (i, j) = (b / 8, b % 8);
+            let low = bytes[i] >> j;
+            let high = bytes
+                .get(i + 1)
+                .map_or(0, |c| c.checked_shl(8 - j as u32).unwrap_or(0));
+            CHARS[((low | high) & 0x1f) as usize] as char
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -157,4 +190,22 @@ mod tests {
         assert_eq!(store_path.hash().unwrap(), original_hash);
         assert_eq!(store_path.name().unwrap(), original_name);
     }
+
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn store_path_hash_part() {
+        let mut store = crate::store::Store::open(Some("dummy://"), []).unwrap();
+        let store_dir = store.get_storedir().unwrap();
+        let store_path = store
+            .parse_store_path(&format!(
+                "{store_dir}/rdd4pnr4x9rqc9wgbibhngv217w2xvxl-bash-interactive-5.2p26"
+            ))
+            .unwrap();
+        assert_eq!(
+            store_path.hash_part().unwrap(),
+            "rdd4pnr4x9rqc9wgbibhngv217w2xvxl"
+        );
+        let zeros = StorePath::from_parts(&[0; STORE_PATH_HASH_SIZE], "foo").unwrap();
+        assert_eq!(zeros.hash_part().unwrap(), "0".repeat(32));
+    }
 }
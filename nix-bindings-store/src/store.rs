@@ -1,9 +1,11 @@
-use anyhow::{bail, Error, Result};
+use anyhow::{bail, Context as _, Error, Result};
 use nix_bindings_store_sys as raw;
-use nix_bindings_util::context::Context;
+use nix_bindings_util::context::{Context, NixError};
 use nix_bindings_util::string_return::{
     callback_get_result_string, callback_get_result_string_data,
 };
+#[cfg(nix_at_least = "2.33.0pre")]
+use nix_bindings_util::thread::setup_current_thread;
 use nix_bindings_util::{check_call, result_string_init};
 use nix_bindings_util_sys as raw_util;
 #[cfg(nix_at_least = "2.33.0pre")]
@@ -12,6 +14,8 @@ use std::collections::HashMap;
 use std::ffi::{c_char, CString};
 use std::ptr::null_mut;
 use std::ptr::NonNull;
+#[cfg(nix_at_least = "2.33.0pre")]
+use std::sync::mpsc::{self, Receiver};
 use std::sync::{Arc, LazyLock, Mutex, Weak};
 
 #[cfg(nix_at_least = "2.33.0pre")]
@@ -19,9 +23,10 @@ use crate::derivation::Derivation;
 use crate::path::StorePath;
 
 /* TODO make Nix itself thread safe */
-static INIT: LazyLock<Result<()>> = LazyLock::new(|| unsafe {
-    check_call!(raw::libstore_init(&mut Context::new()))?;
-    Ok(())
+static INIT: LazyLock<Result<(), NixError>> = LazyLock::new(|| unsafe {
+    let mut ctx = Context::new();
+    raw::libstore_init(ctx.ptr());
+    ctx.get_err().map_or(Ok(()), Err)
 });
 
 struct StoreRef {
@@ -90,6 +95,81 @@ fn callback_get_result_store_path_set_data(vec: &mut Vec<StorePath>) -> *mut std
     vec as *mut Vec<StorePath> as *mut std::os::raw::c_void
 }
 
+/// An event from a build started with [`Store::realise_in_background`].
+#[cfg(nix_at_least = "2.33.0pre")]
+pub enum RealiseEvent {
+    /// The background thread is about to call [`Store::realise`].
+    ///
+    /// This is sent by the thread itself, not reported by Nix. It does not mean that a build or substitution has started:
+    /// the outputs may already be valid, or Nix may still be waiting for locks or build slots.
+    Realising,
+    /// The build has finished, with the same result as [`Store::realise`]. This is the last event.
+    Finished(Result<BTreeMap<String, StorePath>>),
+}
+
+/// A build running on a background thread, started with [`Store::realise_in_background`].
+///
+/// Dropping the handle does not stop the build.
+#[cfg(nix_at_least = "2.33.0pre")]
+pub struct RealiseHandle {
+    events: Receiver<RealiseEvent>,
+}
+#[cfg(nix_at_least = "2.33.0pre")]
+impl RealiseHandle {
+    /// The events of the build, in order. The channel is disconnected after [`RealiseEvent::Finished`].
+    pub fn events(&self) -> &Receiver<RealiseEvent> {
+        &self.events
+    }
+
+    /// Take the event channel, e.g. to move it to another thread or task.
+    pub fn into_events(self) -> Receiver<RealiseEvent> {
+        self.events
+    }
+
+    /// Block until the build has finished, and return its outputs.
+    ///
+    /// Events that have not been received yet are discarded.
+    pub fn wait(self) -> Result<BTreeMap<String, StorePath>> {
+        for event in self.events {
+            if let RealiseEvent::Finished(r) = event {
+                return r;
+            }
+        }
+        bail!("realise thread stopped without a result")
+    }
+}
+
+/// Options for [`Store::copy_path`].
+#[derive(Clone, Debug)]
+pub struct CopyOptions {
+    /// Copy paths again even if they are already valid in the destination store, replacing their contents.
+    ///
+    /// Default: `false`
+    pub repair: bool,
+    /// Check that the paths are signed by a trusted key, if the destination store requires signatures.
+    ///
+    /// Default: `true`
+    pub check_sigs: bool,
+}
+impl Default for CopyOptions {
+    fn default() -> Self {
+        CopyOptions {
+            repair: false,
+            check_sigs: true,
+        }
+    }
+}
+
+/// A Nix store, opened with [`Store::open`].
+///
+/// The C API has no function that adds content to a store, like `addToStore`, `addTextToStore` or `nix-store --import`.
+/// Files can be added through the evaluator instead, e.g. with `builtins.path` or `builtins.toFile`.
+///
+/// Reading the NAR serialization of a store path (`narFromPath`) and exporting paths (`nix-store --export`) are not possible either:
+/// the C API has no `narFromPath`, and the export format also needs the references and deriver of each path.
+///
+/// Garbage collection and GC roots, like `addTempRoot`, `addIndirectRoot`, `findRoots` or `collectGarbage`, are not available,
+/// because the C API does not expose the garbage collector or the store's state directory.
 pub struct Store {
     inner: Arc<StoreRef>,
     /* An error context to reuse. This way we don't have to allocate them for each store operation. */
@@ -99,6 +179,9 @@ impl Store {
     /// Open a store.
     ///
     /// See [`nix_bindings_store_sys::store_open`] for more information.
+    ///
+    /// `params` are [store settings](https://nix.dev/manual/nix/latest/store/types/), such as `root` or `require-sigs`, for this store only.
+    /// Global settings such as `substituters` or `max-jobs` can not be set for a single store; the C API only offers the process-wide [`nix_bindings_util::settings::set`].
     #[doc(alias = "nix_store_open")]
     pub fn open<'a, 'b>(
         url: Option<&str>,
@@ -139,14 +222,7 @@ impl Store {
         url: Option<&str>,
         params: impl IntoIterator<Item = (&'a str, &'b str)>,
     ) -> Result<Self> {
-        let x = INIT.as_ref();
-        match x {
-            Ok(_) => {}
-            Err(e) => {
-                // Couldn't just clone the error, so we have to print it here.
-                bail!("nix_libstore_init error: {}", e);
-            }
-        }
+        INIT.clone().context("nix_libstore_init error")?;
 
         let mut context: Context = Context::new();
 
@@ -257,6 +333,43 @@ impl Store {
         r
     }
 
+    /// Check whether a store path is valid, i.e. whether it exists in the store and is registered.
+    ///
+    /// For a substituting or binary cache store, this may require a network request.
+    ///
+    /// The other metadata of a valid path, such as its NAR hash and size, references, deriver, registration time,
+    /// signatures or content address (`queryPathInfo`), is not available, because the C API does not expose `ValidPathInfo`.
+    #[doc(alias = "nix_store_is_valid_path")]
+    #[doc(alias = "queryPathInfo")]
+    pub fn is_valid_path(&mut self, path: &StorePath) -> Result<bool> {
+        unsafe {
+            check_call!(raw::store_is_valid_path(
+                &mut self.context,
+                self.inner.ptr(),
+                path.as_ptr()
+            ))
+        }
+    }
+
+    /// Look up a valid store path by its hash part, e.g. `rdd4pnr4x9rqc9wgbibhngv217w2xvxl`.
+    ///
+    /// **Requires Nix 2.33 or later.**
+    ///
+    /// Returns `None` if no such path is valid in the store. See [`StorePath::hash_part`] for the hash part of a path.
+    #[cfg(nix_at_least = "2.33.0pre")]
+    #[doc(alias = "nix_store_query_path_from_hash_part")]
+    pub fn query_path_from_hash_part(&mut self, hash_part: &str) -> Result<Option<StorePath>> {
+        let hash_part = CString::new(hash_part)?;
+        unsafe {
+            let path = check_call!(raw::store_query_path_from_hash_part(
+                &mut self.context,
+                self.inner.ptr(),
+                hash_part.as_ptr()
+            ))?;
+            Ok(NonNull::new(path).map(|path| StorePath::new_raw(path)))
+        }
+    }
+
     /// Parse a derivation from JSON.
     ///
     /// **Requires Nix 2.33 or later.**
@@ -314,6 +427,53 @@ impl Store {
         }
     }
 
+    /// Read a derivation (`.drv`) from the store.
+    ///
+    /// **Requires Nix 2.33 or later.**
+    #[cfg(nix_at_least = "2.33.0pre")]
+    #[doc(alias = "nix_store_drv_from_store_path")]
+    #[doc(alias = "readDerivation")]
+    pub fn read_derivation(&mut self, drv_path: &StorePath) -> Result<Derivation> {
+        unsafe {
+            let drv = check_call!(raw::store_drv_from_store_path(
+                &mut self.context,
+                self.inner.ptr(),
+                drv_path.as_ptr()
+            ))?;
+            let inner = NonNull::new(drv)
+                .ok_or_else(|| Error::msg("store_drv_from_store_path returned null"))?;
+            Ok(Derivation::new_raw(inner))
+        }
+    }
+
+    /// Get the output paths of a derivation in the store, by output name, without building it.
+    ///
+    /// **Requires Nix 2.33 or later.**
+    ///
+    /// The paths of input-addressed outputs and of fixed outputs are determined by the derivation.
+    /// The paths of floating content-addressed, deferred and impure outputs are `None`, even if they have been built:
+    /// they are recorded as realisations, which the C API can not query.
+    ///
+    /// Requires the `derivation-data` feature.
+    #[cfg(all(feature = "derivation-data", nix_at_least = "2.33"))]
+    #[doc(alias = "queryPartialDerivationOutputMap")]
+    pub fn query_derivation_outputs(
+        &mut self,
+        drv_path: &StorePath,
+    ) -> Result<BTreeMap<String, Option<StorePath>>> {
+        let drv = self.read_derivation(drv_path)?.to_data()?;
+        let store_dir = self.get_storedir()?;
+        let mut outputs = BTreeMap::new();
+        for (name, output) in &drv.outputs {
+            let path = match output.path(&store_dir, &drv.name, name)? {
+                Some(path) => Some(self.parse_store_path(&format!("{store_dir}/{path}"))?),
+                None => None,
+            };
+            outputs.insert(name.clone(), path);
+        }
+        Ok(outputs)
+    }
+
     /// Build a derivation and return its outputs.
     ///
     /// **Requires Nix 2.33 or later.**
@@ -328,6 +488,10 @@ impl Store {
     /// # Returns
     /// A [`BTreeMap`] mapping output names (e.g., "out", "dev", "doc") to their store paths.
     /// The map is ordered alphabetically by output name for deterministic iteration.
+    ///
+    /// It does not report whether the outputs were built, substituted or already valid.
+    /// The C API builds one derivation at a time, so there is no equivalent of `buildPathsWithResults`;
+    /// call this for each derivation instead.
     #[cfg(nix_at_least = "2.33.0pre")]
     #[doc(alias = "nix_store_realise")]
     pub fn realise(&mut self, path: &StorePath) -> Result<BTreeMap<String, StorePath>> {
@@ -367,6 +531,44 @@ impl Store {
         Ok(outputs)
     }
 
+    /// Build a derivation on a background thread.
+    ///
+    /// **Requires Nix 2.33 or later.**
+    ///
+    /// This is the non-blocking counterpart of [`Store::realise`].
+    /// The returned [`RealiseHandle`] provides a channel of [`RealiseEvent`]s, ending with the outputs of the build.
+    ///
+    /// The Nix C API does not report log lines, substitution progress or the state of Nix's scheduler, and it can not interrupt a build,
+    /// so only the call to [`Store::realise`] and its result are reported, and the build runs to completion even if the handle is dropped.
+    #[cfg(nix_at_least = "2.33.0pre")]
+    pub fn realise_in_background(&self, path: &StorePath) -> Result<RealiseHandle> {
+        let (sender, events) = mpsc::channel();
+        let inner = self.inner.clone();
+        let path = path.clone();
+        std::thread::Builder::new()
+            .name("nix realise".to_owned())
+            .spawn(move || {
+                // Sending fails when the handle is dropped, which is fine.
+                // Registers the thread with the garbage collector, if nix_bindings_expr is in use.
+                let thread_setup = match setup_current_thread() {
+                    Ok(guard) => guard,
+                    Err(e) => {
+                        let _ = sender.send(RealiseEvent::Finished(Err(e)));
+                        return;
+                    }
+                };
+                let mut store = Store {
+                    inner,
+                    context: Context::new(),
+                };
+                let _ = sender.send(RealiseEvent::Realising);
+                let _ = sender.send(RealiseEvent::Finished(store.realise(&path)));
+                drop(store);
+                drop(thread_setup);
+            })?;
+        Ok(RealiseHandle { events })
+    }
+
     /// Get the closure of a specific store path.
     ///
     /// **Requires Nix 2.33 or later.**
@@ -385,6 +587,9 @@ impl Store {
     ///
     /// # Returns
     /// A vector of store paths in the closure, in no particular order.
+    ///
+    /// The C API has no path info queries, so the direct references, referrers, deriver or valid derivers of a path
+    /// can not be queried on their own; this closure is the closest alternative.
     #[cfg(nix_at_least = "2.33.0pre")]
     #[doc(alias = "nix_store_get_fs_closure")]
     pub fn get_fs_closure(
@@ -410,6 +615,68 @@ impl Store {
         Ok(r)
     }
 
+    /// Copy the closures of store paths to another store.
+    ///
+    /// **Requires Nix 2.33 or later.**
+    ///
+    /// Paths that are already valid in `dst` are skipped.
+    /// The paths must be valid in this store; they are not substituted.
+    /// Substituting them on the destination instead, like `nix copy --substitute-on-destination`, is not supported,
+    /// because the C API does not take a `substitute` flag.
+    ///
+    /// Nix copies the paths in reference order. `nix_store_copy_closure` does not take any options:
+    /// it always checks signatures and does not repair.
+    /// To skip signature checks, open a local destination store with `require-sigs=false`.
+    /// To repair paths, or to skip signature checks for other stores, copy them with [`copy_path`][Store::copy_path].
+    ///
+    /// # Parameters
+    /// - `dst`: The store to copy to, for example a `file://` binary cache or another local store
+    /// - `paths`: The paths whose closures to copy
+    #[cfg(nix_at_least = "2.33.0pre")]
+    #[doc(alias = "nix_store_copy_closure")]
+    #[doc(alias = "copyClosure")]
+    pub fn copy_closure<'a>(
+        &mut self,
+        dst: &Store,
+        paths: impl IntoIterator<Item = &'a StorePath>,
+    ) -> Result<()> {
+        for path in paths {
+            unsafe {
+                check_call!(raw::store_copy_closure(
+                    &mut self.context,
+                    self.inner.ptr(),
+                    dst.inner.ptr(),
+                    path.as_ptr()
+                ))
+            }?;
+        }
+        Ok(())
+    }
+
+    /// Copy a single store path to another store.
+    ///
+    /// **Requires Nix 2.33 or later.**
+    ///
+    /// Unlike [`Store::copy_closure`], this does not copy the references of the path.
+    /// Depending on the destination store, they may have to be valid there already.
+    /// Like [`Store::copy_closure`], it does not substitute the path on the destination.
+    #[cfg(nix_at_least = "2.33.0pre")]
+    #[doc(alias = "nix_store_copy_path")]
+    #[doc(alias = "copyStorePath")]
+    pub fn copy_path(&mut self, dst: &Store, path: &StorePath, opts: &CopyOptions) -> Result<()> {
+        unsafe {
+            check_call!(raw::store_copy_path(
+                &mut self.context,
+                self.inner.ptr(),
+                dst.inner.ptr(),
+                path.as_ptr(),
+                opts.repair,
+                opts.check_sigs
+            ))
+        }?;
+        Ok(())
+    }
+
     pub fn weak_ref(&self) -> StoreWeak {
         StoreWeak {
             inner: Arc::downgrade(&self.inner),
@@ -439,7 +706,7 @@ mod tests {
         let _ = INIT.as_ref();
 
         // Enable ca-derivations for all tests
-        nix_bindings_util::settings::set("experimental-features", "ca-derivations").ok();
+        nix_bindings_util::settings::set_experimental_features(["ca-derivations"]).ok();
 
         // Disable build hooks to prevent test recursion
         nix_bindings_util::settings::set("build-hook", "").ok();
@@ -453,7 +720,7 @@ mod tests {
         std::env::set_var("_NIX_TEST_NO_SANDBOX", "1");
 
         // Tests run offline
-        nix_bindings_util::settings::set("substituters", "").ok();
+        nix_bindings_util::settings::set_substituters([]).ok();
     }
 
     #[test]
@@ -541,6 +808,19 @@ mod tests {
         assert!(weak.inner.upgrade().is_none());
     }
 
+    #[test]
+    #[cfg(nix_at_least = "2.26" /* get_storedir */)]
+    fn is_valid_path_missing() {
+        let mut store = crate::store::Store::open(Some("dummy://"), []).unwrap();
+        let store_dir = store.get_storedir().unwrap();
+        let store_path = store
+            .parse_store_path(&format!(
+                "{store_dir}/rdd4pnr4x9rqc9wgbibhngv217w2xvxl-bash-interactive-5.2p26"
+            ))
+            .unwrap();
+        assert!(!store.is_valid_path(&store_path).unwrap());
+    }
+
     #[cfg(nix_at_least = "2.33.0pre")]
     fn create_temp_store() -> (Store, tempfile::TempDir) {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -563,8 +843,32 @@ mod tests {
         (store, temp_dir)
     }
 
+    /// A local store that shares the store directory of `store`, but keeps its files elsewhere, so that paths can be copied between them.
+    ///
+    /// It does not require signatures, because locally built paths are not signed.
+    #[cfg(nix_at_least = "2.33.0pre")]
+    fn create_temp_store_like(store: &mut Store) -> (Store, tempfile::TempDir) {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let store_dir = store.get_storedir().unwrap();
+
+        let real_dir = temp_dir.path().join("store");
+        let state_dir = temp_dir.path().join("state");
+        let log_dir = temp_dir.path().join("log");
+
+        let params = vec![
+            ("store", store_dir.as_str()),
+            ("real", real_dir.to_str().unwrap()),
+            ("state", state_dir.to_str().unwrap()),
+            ("log", log_dir.to_str().unwrap()),
+            ("require-sigs", "false"),
+        ];
+
+        let store = Store::open(Some("local"), params).unwrap();
+        (store, temp_dir)
+    }
+
     fn current_system() -> Result<String> {
-        nix_bindings_util::settings::get("system")
+        nix_bindings_util::settings::system()
     }
 
     #[cfg(nix_at_least = "2.33")]
@@ -659,6 +963,195 @@ mod tests {
         drop(temp_dir);
     }
 
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn is_valid_path_added_derivation() {
+        let (mut store, temp_dir) = create_temp_store();
+        let drv_json = create_test_derivation_json();
+        let drv = store.derivation_from_json(&drv_json.to_string()).unwrap();
+        let drv_path = store.add_derivation(&drv).unwrap();
+        assert!(store.is_valid_path(&drv_path).unwrap());
+
+        // Never added
+        let store_dir = store.get_storedir().unwrap();
+        let other_path = store
+            .parse_store_path(&format!(
+                "{store_dir}/rdd4pnr4x9rqc9wgbibhngv217w2xvxl-myname"
+            ))
+            .unwrap();
+        assert!(!store.is_valid_path(&other_path).unwrap());
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn read_derivation() {
+        let (mut store, temp_dir) = create_temp_store();
+        let drv_json = create_test_derivation_json();
+        let drv = store.derivation_from_json(&drv_json.to_string()).unwrap();
+        let drv_path = store.add_derivation(&drv).unwrap();
+
+        let read = store.read_derivation(&drv_path).unwrap();
+        assert_eq!(
+            read.to_json_string().unwrap(),
+            drv.to_json_string().unwrap()
+        );
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn read_derivation_not_a_derivation() {
+        let (mut store, temp_dir) = create_temp_store();
+        let store_dir = store.get_storedir().unwrap();
+        let path = store
+            .parse_store_path(&format!(
+                "{store_dir}/rdd4pnr4x9rqc9wgbibhngv217w2xvxl-myname"
+            ))
+            .unwrap();
+        assert!(store.read_derivation(&path).is_err());
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    #[cfg(all(feature = "derivation-data", nix_at_least = "2.33"))]
+    fn query_derivation_outputs_ca() {
+        let (mut store, temp_dir) = create_temp_store();
+        let drv_json = create_test_derivation_json();
+        let drv = store.derivation_from_json(&drv_json.to_string()).unwrap();
+        let drv_path = store.add_derivation(&drv).unwrap();
+
+        // Content-addressed, so unknown before and after building
+        let outputs = store.query_derivation_outputs(&drv_path).unwrap();
+        assert_eq!(outputs.keys().collect::<Vec<_>>(), ["out"]);
+        assert!(outputs["out"].is_none());
+
+        store.realise(&drv_path).unwrap();
+        let outputs = store.query_derivation_outputs(&drv_path).unwrap();
+        assert!(outputs["out"].is_none());
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    #[cfg(all(feature = "derivation-data", nix_at_least = "2.33"))]
+    fn query_derivation_outputs_fixed() {
+        use crate::derivation::{
+            ContentAddressMethod::{self, *},
+            DerivationBuilder, DerivationOutput,
+        };
+
+        // The hashes of an empty file, for each branch of `DerivationOutput::path`.
+        // Text and git hashing require the `dynamic-derivations` and `git-hashing` experimental features.
+        let cases: [(ContentAddressMethod, &str); 10] = [
+            (Flat, "md5-1B2M2Y8AsgTpgAmY7PhCfg=="),
+            (Flat, "sha1-2jmj7l5rSw0yVb/vlWAYkK/YBwk="),
+            (Flat, "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="),
+            (Flat, "sha512-z4PhNX7vuL3xVChQ1m2AB9Yg5AULVxXcg/SpIdNs6c5H0NE8XYXysP+DGNKHfuwvY7kxvUdBeoGlODJ6+SfaPg=="),
+            (Nar, "md5-2+NSJ/1ZYBm6zkxdONG+Iw=="),
+            (Nar, "sha1-CbiSGalS68RRRseS8I4kRYu27io="),
+            (Nar, "sha256-d6xi4mKdjkX2JFicDIv5niSzpyI0m/Hnm8GGAIU04kY="),
+            (Text, "sha256-47DEQpj8HBSa+/TImW+5JCeuQeRkm5NMpJWZG3hSuFU="),
+            (Git, "sha1-5p3im7LR1kNLiymud1rYwuSMU5E="),
+            (Git, "sha256-RzoPTDvoqTaBomfjsemn3NoRhUNv4UH3dJEgowNyGBM="),
+        ];
+
+        let (mut store, temp_dir) = create_temp_store();
+        let store_dir = store.get_storedir().unwrap();
+        for (method, hash) in cases {
+            let name = format!(
+                "fixed-{}-{}",
+                method.as_str(),
+                hash.split('-').next().unwrap()
+            );
+            let output = DerivationOutput::CaFixed {
+                method,
+                hash: hash.to_owned(),
+            };
+            let path = output.path(&store_dir, &name, "out").unwrap().unwrap();
+            // Nix checks that the environment has the right output path
+            let drv = DerivationBuilder::new(&name, current_system().unwrap(), "/bin/sh")
+                .args(["-c", ": > $out"])
+                .output("out", output)
+                .env("out", format!("{store_dir}/{path}"))
+                .build(&mut store)
+                .unwrap();
+            let drv_path = store.add_derivation(&drv).unwrap();
+
+            let outputs = store.query_derivation_outputs(&drv_path).unwrap();
+            let expected = outputs["out"].as_ref().unwrap();
+            assert_eq!(
+                store.real_path(expected).unwrap(),
+                format!("{store_dir}/{path}"),
+                "{name}"
+            );
+
+            // The output path is the one that Nix computes when it checks the hash of the build result
+            let built = store.realise(&drv_path).unwrap();
+            assert_eq!(
+                store.real_path(&built["out"]).unwrap(),
+                store.real_path(expected).unwrap(),
+                "{name}"
+            );
+        }
+
+        // Rejected by Nix as well
+        for (method, hash) in [
+            (Text, "sha1-2jmj7l5rSw0yVb/vlWAYkK/YBwk="),
+            (Git, "md5-1B2M2Y8AsgTpgAmY7PhCfg=="),
+        ] {
+            let output = DerivationOutput::CaFixed {
+                method,
+                hash: hash.to_owned(),
+            };
+            assert!(output.path(&store_dir, "fixed", "out").is_err());
+            let r = DerivationBuilder::new("fixed", current_system().unwrap(), "/bin/sh")
+                .output("out", output)
+                .env(
+                    "out",
+                    format!("{store_dir}/00000000000000000000000000000000-fixed"),
+                )
+                .build(&mut store);
+            assert!(r.is_err(), "{}", method.as_str());
+        }
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn query_path_from_hash_part() {
+        let (mut store, temp_dir) = create_temp_store();
+        let drv_json = create_test_derivation_json();
+        let drv = store.derivation_from_json(&drv_json.to_string()).unwrap();
+        let drv_path = store.add_derivation(&drv).unwrap();
+
+        let hash_part = drv_path.hash_part().unwrap();
+        let found = store
+            .query_path_from_hash_part(&hash_part)
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            store.real_path(&found).unwrap(),
+            store.real_path(&drv_path).unwrap()
+        );
+        assert!(store
+            .query_path_from_hash_part("rdd4pnr4x9rqc9wgbibhngv217w2xvxl")
+            .unwrap()
+            .is_none());
+
+        drop(store);
+        drop(temp_dir);
+    }
+
     #[test]
     #[cfg(nix_at_least = "2.33")]
     fn realise() {
@@ -680,6 +1173,50 @@ mod tests {
         drop(temp_dir);
     }
 
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn realise_in_background() {
+        let (mut store, temp_dir) = create_temp_store();
+        let drv_json = create_test_derivation_json();
+        let drv = store.derivation_from_json(&drv_json.to_string()).unwrap();
+        let drv_path = store.add_derivation(&drv).unwrap();
+
+        let handle = store.realise_in_background(&drv_path).unwrap();
+        let events: Vec<RealiseEvent> = handle.into_events().into_iter().collect();
+        match events.as_slice() {
+            [RealiseEvent::Realising, RealiseEvent::Finished(Ok(outputs))] => {
+                assert_eq!(outputs["out"].name().unwrap(), "myname");
+            }
+            _ => panic!("expected Realising and a successful Finished event"),
+        }
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn realise_in_background_wait_fails() {
+        let (mut store, temp_dir) = create_temp_store();
+        let mut drv_json = create_test_derivation_json();
+        drv_json["args"] = serde_json::json!(["-c", "exit 1"]);
+        let drv = store.derivation_from_json(&drv_json.to_string()).unwrap();
+        let drv_path = store.add_derivation(&drv).unwrap();
+
+        let handle = store.realise_in_background(&drv_path).unwrap();
+        match handle.wait() {
+            Ok(_) => panic!("Build should fail when builder exits with error"),
+            Err(e) => assert!(
+                e.to_string().contains("builder failed with exit code 1"),
+                "got: {}",
+                e
+            ),
+        }
+
+        drop(store);
+        drop(temp_dir);
+    }
+
     #[cfg(nix_at_least = "2.33")]
     fn create_multi_output_derivation_json() -> serde_json::Value {
         let system = current_system()
@@ -1016,4 +1553,75 @@ mod tests {
         drop(store);
         drop(temp_dir);
     }
+
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn copy_closure_to_binary_cache() {
+        let (mut store, temp_dir) = create_temp_store();
+        let drv_json = create_test_derivation_json();
+        let drv = store.derivation_from_json(&drv_json.to_string()).unwrap();
+        let drv_path = store.add_derivation(&drv).unwrap();
+        let outputs = store.realise(&drv_path).unwrap();
+        let out_path = &outputs["out"];
+
+        let cache_dir = tempfile::tempdir().unwrap();
+        let cache_uri = format!("file://{}", cache_dir.path().to_str().unwrap());
+        let store_dir = store.get_storedir().unwrap();
+        let mut cache = Store::open(Some(&cache_uri), [("store", store_dir.as_str())]).unwrap();
+        assert!(!cache.is_valid_path(out_path).unwrap());
+
+        store.copy_closure(&cache, [out_path]).unwrap();
+        assert!(cache.is_valid_path(out_path).unwrap());
+        assert!(!cache.is_valid_path(&drv_path).unwrap());
+
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn copy_closure_between_local_stores() {
+        let (mut store, temp_dir) = create_temp_store();
+        let drv_json = create_test_derivation_json();
+        let drv = store.derivation_from_json(&drv_json.to_string()).unwrap();
+        let drv_path = store.add_derivation(&drv).unwrap();
+        let outputs = store.realise(&drv_path).unwrap();
+        let out_path = &outputs["out"];
+
+        let (mut dst, dst_dir) = create_temp_store_like(&mut store);
+        store.copy_closure(&dst, [&drv_path, out_path]).unwrap();
+        assert!(dst.is_valid_path(&drv_path).unwrap());
+        assert!(dst.is_valid_path(out_path).unwrap());
+
+        // Copying again is a no-op
+        store.copy_closure(&dst, [&drv_path, out_path]).unwrap();
+
+        drop(dst);
+        drop(dst_dir);
+        drop(store);
+        drop(temp_dir);
+    }
+
+    #[test]
+    #[cfg(nix_at_least = "2.33")]
+    fn copy_path() {
+        let (mut store, temp_dir) = create_temp_store();
+        let drv_json = create_test_derivation_json();
+        let drv = store.derivation_from_json(&drv_json.to_string()).unwrap();
+        let drv_path = store.add_derivation(&drv).unwrap();
+
+        let (mut dst, dst_dir) = create_temp_store_like(&mut store);
+        assert!(!dst.is_valid_path(&drv_path).unwrap());
+        let opts = CopyOptions {
+            check_sigs: false,
+            ..Default::default()
+        };
+        store.copy_path(&dst, &drv_path, &opts).unwrap();
+        assert!(dst.is_valid_path(&drv_path).unwrap());
+
+        drop(dst);
+        drop(dst_dir);
+        drop(store);
+        drop(temp_dir);
+    }
 }
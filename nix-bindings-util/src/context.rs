@@ -1,8 +1,116 @@
-use anyhow::{bail, Result};
+use anyhow::Result;
 use nix_bindings_util_sys as raw;
+use std::fmt;
 use std::ptr::null_mut;
 use std::ptr::NonNull;
 
+use crate::result_string_init;
+use crate::string_return::{callback_get_result_string, callback_get_result_string_data};
+
+/// The category of a [`NixError`], derived from the C API's `nix_err` code, and for [`Restricted`][NixErrorKind::Restricted], the [name][NixError::name] of the error.
+///
+/// More kinds may be added, so a `match` needs a wildcard arm.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+pub enum NixErrorKind {
+    /// `NIX_ERR_KEY`: a key, such as an attribute name or setting name, does not exist.
+    Key,
+    /// `NIX_ERR_OVERFLOW`: an overflow occurred, or a buffer was too small.
+    Overflow,
+    /// `NIX_ERR_NIX_ERROR`: an error was thrown by Nix itself, e.g. during evaluation.
+    ///
+    /// Only errors of this kind and [`Restricted`][NixErrorKind::Restricted] have a [`NixError::name`] and [`NixError::info_msg`].
+    NixError,
+    /// A `NIX_ERR_NIX_ERROR` for a path or URI access that is forbidden by pure or restricted evaluation, such as reading a file outside the allowed paths.
+    ///
+    /// Only such accesses are classified, by their error name [`RESTRICTED_PATH_ERROR`].
+    /// Other operations that pure evaluation forbids, such as a `<nixpkgs>` lookup or `builtins.storePath`, fail with a [`NixError`][NixErrorKind::NixError].
+    Restricted,
+    /// `NIX_ERR_UNKNOWN`, or a code that is not known to these bindings.
+    Unknown,
+}
+
+/// The [name][NixError::name] of the Nix error type for forbidden path and URI accesses, classified as [`NixErrorKind::Restricted`].
+pub const RESTRICTED_PATH_ERROR: &str = "nix::RestrictedPathError";
+
+impl NixErrorKind {
+    fn from_raw(code: raw::err, name: Option<&str>) -> Self {
+        match code {
+            raw::err_NIX_ERR_KEY => NixErrorKind::Key,
+            raw::err_NIX_ERR_OVERFLOW => NixErrorKind::Overflow,
+            raw::err_NIX_ERR_NIX_ERROR if name == Some(RESTRICTED_PATH_ERROR) => {
+                NixErrorKind::Restricted
+            }
+            raw::err_NIX_ERR_NIX_ERROR => NixErrorKind::NixError,
+            _ => NixErrorKind::Unknown,
+        }
+    }
+}
+
+/// An error reported by the Nix C API.
+///
+/// Methods in the `nix-bindings-*` crates return [`anyhow::Error`], which can be downcast to this type when the error originated in Nix:
+///
+/// ```
+/// # use nix_bindings_util::context::{NixError, NixErrorKind};
+/// fn is_missing_key(e: &anyhow::Error) -> bool {
+///     e.downcast_ref::<NixError>()
+///         .is_some_and(|e| e.kind() == NixErrorKind::Key)
+/// }
+/// ```
+///
+/// Its [`Display`][fmt::Display] implementation renders the same message as `nix_err_msg`.
+#[derive(Clone, Debug)]
+pub struct NixError {
+    kind: NixErrorKind,
+    code: raw::err,
+    name: Option<String>,
+    info_msg: Option<String>,
+    msg: String,
+}
+
+impl NixError {
+    /// The category of the error.
+    pub fn kind(&self) -> NixErrorKind {
+        self.kind
+    }
+
+    /// The raw `nix_err` code, e.g. [`nix_bindings_util_sys::err_NIX_ERR_KEY`].
+    pub fn code(&self) -> raw::err {
+        self.code
+    }
+
+    /// The name of the Nix error type, such as `nix::EvalError` or `nix::ThrownError`.
+    ///
+    /// Only available for [`NixErrorKind::NixError`] and [`NixErrorKind::Restricted`].
+    #[doc(alias = "nix_err_name")]
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// The error message without the additional context that Nix may add, such as a stack trace.
+    ///
+    /// Only available for [`NixErrorKind::NixError`] and [`NixErrorKind::Restricted`].
+    #[doc(alias = "nix_err_info_msg")]
+    pub fn info_msg(&self) -> Option<&str> {
+        self.info_msg.as_deref()
+    }
+
+    /// The full error message.
+    #[doc(alias = "nix_err_msg")]
+    pub fn msg(&self) -> &str {
+        &self.msg
+    }
+}
+
+impl fmt::Display for NixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.msg)
+    }
+}
+
+impl std::error::Error for NixError {}
+
 /// A context for error handling, when interacting directly with the generated bindings for the C API in [nix_bindings_util_sys].
 ///
 /// The `nix-store` and `nix-expr` libraries that consume this type internally store a private context in their `EvalState` and `Store` structs to avoid allocating a new context for each operation. The state of a context is irrelevant when used correctly (e.g. with [check_call!]), so it's safe to reuse, and safe to allocate more contexts in methods such as [Clone::clone].
@@ -38,17 +146,66 @@ impl Context {
 
     /// Check the error code and return an error if it's not `NIX_OK`.
     ///
+    /// The returned error can be downcast to a [`NixError`].
+    ///
     /// We recommend to use `check_call!` if possible.
     pub fn check_err(&self) -> Result<()> {
-        let err = unsafe { raw::err_code(self.inner.as_ptr()) };
-        if err != raw::err_NIX_OK {
-            // msgp is a borrowed pointer (pointing into the context), so we don't need to free it
-            let msgp = unsafe { raw::err_msg(null_mut(), self.inner.as_ptr(), null_mut()) };
-            // Turn the i8 pointer into a Rust string by copying
-            let msg: &str = unsafe { core::ffi::CStr::from_ptr(msgp).to_str()? };
-            bail!("{}", msg);
+        match self.get_err() {
+            Some(e) => Err(e.into()),
+            None => Ok(()),
+        }
+    }
+
+    /// Read the error stored in the context, if any.
+    pub fn get_err(&self) -> Option<NixError> {
+        let code = unsafe { raw::err_code(self.inner.as_ptr()) };
+        if code == raw::err_NIX_OK {
+            return None;
+        }
+        // msgp is a borrowed pointer (pointing into the context), so we don't need to free it
+        let msgp = unsafe { raw::err_msg(null_mut(), self.inner.as_ptr(), null_mut()) };
+        // Turn the i8 pointer into a Rust string by copying
+        let msg = unsafe { core::ffi::CStr::from_ptr(msgp) }
+            .to_string_lossy()
+            .into_owned();
+        let (name, info_msg) = if code == raw::err_NIX_ERR_NIX_ERROR {
+            (
+                self.read_err_string(raw::err_name),
+                self.read_err_string(raw::err_info_msg),
+            )
+        } else {
+            (None, None)
+        };
+        let kind = NixErrorKind::from_raw(code, name.as_deref());
+        Some(NixError {
+            kind,
+            code,
+            name,
+            info_msg,
+            msg,
+        })
+    }
+
+    /// Read a string detail of a `NIX_ERR_NIX_ERROR`, such as `nix_err_name`.
+    fn read_err_string(
+        &self,
+        f: unsafe extern "C" fn(
+            *mut raw::c_context,
+            *const raw::c_context,
+            raw::get_string_callback,
+            *mut std::os::raw::c_void,
+        ) -> raw::err,
+    ) -> Option<String> {
+        let mut r = result_string_init!();
+        unsafe {
+            f(
+                null_mut(),
+                self.inner.as_ptr(),
+                Some(callback_get_result_string),
+                callback_get_result_string_data(&mut r),
+            );
         }
-        Ok(())
+        r.ok()
     }
 
     pub fn clear(&mut self) {
@@ -156,4 +313,51 @@ mod tests {
         assert!(r.is_err());
         assert_eq!(r.unwrap_err().to_string(), "dummy error message");
     }
+
+    #[test]
+    fn check_call_nix_error_downcast() {
+        let r = check_call!(set_dummy_err(&mut Context::new()));
+        let e = r.unwrap_err();
+        let e = e.downcast_ref::<NixError>().unwrap();
+        assert_eq!(e.kind(), NixErrorKind::Unknown);
+        assert_eq!(e.code(), raw::err_NIX_ERR_UNKNOWN);
+        assert_eq!(e.msg(), "dummy error message");
+        assert_eq!(e.name(), None);
+        assert_eq!(e.info_msg(), None);
+    }
+
+    fn set_key_err(ctx_ptr: *mut raw::c_context) {
+        unsafe {
+            raw::set_err_msg(ctx_ptr, raw::err_NIX_ERR_KEY, c"no such key".as_ptr());
+        }
+    }
+
+    #[test]
+    fn check_call_nix_error_key() {
+        let mut ctx = Context::new();
+        let e = check_call!(set_key_err(&mut ctx)).unwrap_err();
+        let e = e.downcast_ref::<NixError>().unwrap();
+        assert_eq!(e.kind(), NixErrorKind::Key);
+        assert_eq!(e.to_string(), "no such key");
+        // check_call! clears the context
+        assert!(ctx.get_err().is_none());
+    }
+
+    #[test]
+    fn nix_error_kind_restricted() {
+        // The name of the error type in libexpr
+        assert_eq!(RESTRICTED_PATH_ERROR, "nix::RestrictedPathError");
+        assert_eq!(
+            NixErrorKind::from_raw(raw::err_NIX_ERR_NIX_ERROR, Some("nix::RestrictedPathError")),
+            NixErrorKind::Restricted
+        );
+        assert_eq!(
+            NixErrorKind::from_raw(raw::err_NIX_ERR_NIX_ERROR, Some("nix::EvalError")),
+            NixErrorKind::NixError
+        );
+        assert_eq!(
+            NixErrorKind::from_raw(raw::err_NIX_ERR_KEY, Some("nix::RestrictedPathError")),
+            NixErrorKind::Key
+        );
+    }
 }
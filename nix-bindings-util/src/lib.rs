@@ -3,6 +3,8 @@ pub mod settings;
 #[macro_use]
 pub mod string_return;
 pub mod nix_version;
+pub mod thread;
+pub mod verbosity;
 
 // Re-export for use in macros
 pub use nix_bindings_util_sys as raw_sys;
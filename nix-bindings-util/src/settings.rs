@@ -1,5 +1,6 @@
-use anyhow::Result;
+use anyhow::{bail, Context as _, Result};
 use nix_bindings_util_sys as raw;
+use std::collections::BTreeSet;
 use std::sync::Mutex;
 
 use crate::{
@@ -42,6 +43,8 @@ pub fn set(key: &str, value: &str) -> Result<()> {
 
 /// Get a Nix setting.
 ///
+/// Settings can only be looked up by name: the C API can not enumerate all settings with their descriptions and defaults.
+///
 /// # Thread Safety
 ///
 /// See the documentation on [`set()`] for important thread safety information.
@@ -64,6 +67,214 @@ pub fn get(key: &str) -> Result<String> {
     r
 }
 
+// Typed accessors
+//
+// Nix settings that are lists are stored as whitespace separated strings.
+
+fn get_list(key: &str) -> Result<Vec<String>> {
+    Ok(get(key)?.split_whitespace().map(str::to_owned).collect())
+}
+
+fn set_list<'a>(key: &str, items: impl IntoIterator<Item = &'a str>) -> Result<()> {
+    let items: Vec<&str> = items.into_iter().collect();
+    for item in &items {
+        if item.is_empty() || item.contains(char::is_whitespace) {
+            bail!("invalid item {:?} for setting `{}`", item, key);
+        }
+    }
+    set(key, &items.join(" "))
+}
+
+fn get_parsed<T: std::str::FromStr>(key: &str) -> Result<T>
+where
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    let value = get(key)?;
+    value
+        .parse()
+        .with_context(|| format!("could not parse setting `{}` value {:?}", key, value))
+}
+
+/// Get the [`experimental-features`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-experimental-features) setting.
+pub fn experimental_features() -> Result<BTreeSet<String>> {
+    Ok(get_list("experimental-features")?.into_iter().collect())
+}
+
+/// Set the [`experimental-features`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-experimental-features) setting, replacing the enabled features.
+pub fn set_experimental_features<'a>(features: impl IntoIterator<Item = &'a str>) -> Result<()> {
+    set_list("experimental-features", features)
+}
+
+/// The value of the [`max-jobs`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-max-jobs) setting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MaxJobs {
+    /// One job per CPU.
+    Auto,
+    /// At most this many jobs. `0` disables local builds.
+    Count(u32),
+}
+
+/// Get the [`max-jobs`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-max-jobs) setting.
+///
+/// Nix resolves `auto` to the number of CPUs when the setting is set, so this normally returns [`MaxJobs::Count`].
+pub fn max_jobs() -> Result<MaxJobs> {
+    match get("max-jobs")?.as_str() {
+        "auto" => Ok(MaxJobs::Auto),
+        value => value
+            .parse()
+            .map(MaxJobs::Count)
+            .with_context(|| format!("could not parse setting `max-jobs` value {:?}", value)),
+    }
+}
+
+/// Set the [`max-jobs`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-max-jobs) setting.
+pub fn set_max_jobs(max_jobs: MaxJobs) -> Result<()> {
+    match max_jobs {
+        MaxJobs::Auto => set("max-jobs", "auto"),
+        MaxJobs::Count(n) => set("max-jobs", &n.to_string()),
+    }
+}
+
+/// Get the [`cores`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-cores) setting. `0` means all cores.
+pub fn cores() -> Result<u32> {
+    get_parsed("cores")
+}
+
+/// Set the [`cores`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-cores) setting. `0` means all cores.
+pub fn set_cores(cores: u32) -> Result<()> {
+    set("cores", &cores.to_string())
+}
+
+/// Get the [`substituters`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-substituters) setting: the stores to substitute from.
+///
+/// The entries are returned as configured, as unparsed strings, not as URLs.
+/// They are [store references](https://nix.dev/manual/nix/latest/store/types/), which are not all URLs:
+/// Nix also accepts a local store's root directory, such as `/mnt/nix`, and store types without an authority, such as `daemon`.
+/// A URL type would fail on such values, even though Nix accepts them.
+pub fn substituters() -> Result<Vec<String>> {
+    get_list("substituters")
+}
+
+/// Set the [`substituters`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-substituters) setting.
+///
+/// Each substituter must be a URL, such as `https://cache.nixos.org`.
+/// Only the form `scheme://rest` is checked; whether Nix supports the scheme is only known when it opens the store.
+/// Other store references, such as a local store's root directory, can be set with [`set`].
+pub fn set_substituters<'a>(substituters: impl IntoIterator<Item = &'a str>) -> Result<()> {
+    let substituters: Vec<&str> = substituters.into_iter().collect();
+    for substituter in &substituters {
+        let valid = match substituter.split_once("://") {
+            Some((scheme, rest)) => {
+                !scheme.is_empty()
+                    && scheme
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c))
+                    && !rest.is_empty()
+            }
+            None => false,
+        };
+        if !valid {
+            bail!("invalid substituter {:?}, expected a URL", substituter);
+        }
+    }
+    set_list("substituters", substituters)
+}
+
+/// Get the [`trusted-public-keys`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-trusted-public-keys) setting.
+pub fn trusted_public_keys() -> Result<Vec<String>> {
+    get_list("trusted-public-keys")
+}
+
+/// Set the [`trusted-public-keys`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-trusted-public-keys) setting.
+///
+/// Each key has the form `name:base64-key`, e.g. `cache.nixos.org-1:6NCHdD59X431o0gWypbMrAURkbJ16ZPMQFGspcDShjY=`.
+pub fn set_trusted_public_keys<'a>(keys: impl IntoIterator<Item = &'a str>) -> Result<()> {
+    let keys: Vec<&str> = keys.into_iter().collect();
+    for key in &keys {
+        let valid = match key.split_once(':') {
+            Some((name, key)) => {
+                !name.is_empty()
+                    && !key.is_empty()
+                    && key
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || "+/=".contains(c))
+            }
+            None => false,
+        };
+        if !valid {
+            bail!("invalid public key {:?}, expected name:base64-key", key);
+        }
+    }
+    set_list("trusted-public-keys", keys)
+}
+
+/// The value of the [`sandbox`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-sandbox) setting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SandboxMode {
+    Enabled,
+    Disabled,
+    /// Derivations with `__noChroot = true` are built without a sandbox.
+    Relaxed,
+}
+
+/// Get the [`sandbox`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-sandbox) setting.
+pub fn sandbox() -> Result<SandboxMode> {
+    match get("sandbox")?.as_str() {
+        "true" => Ok(SandboxMode::Enabled),
+        "false" => Ok(SandboxMode::Disabled),
+        "relaxed" => Ok(SandboxMode::Relaxed),
+        value => bail!("could not parse setting `sandbox` value {:?}", value),
+    }
+}
+
+/// Set the [`sandbox`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-sandbox) setting.
+pub fn set_sandbox(mode: SandboxMode) -> Result<()> {
+    let value = match mode {
+        SandboxMode::Enabled => "true",
+        SandboxMode::Disabled => "false",
+        SandboxMode::Relaxed => "relaxed",
+    };
+    set("sandbox", value)
+}
+
+/// Get the [`system`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-system) setting, e.g. `x86_64-linux`.
+pub fn system() -> Result<String> {
+    get("system")
+}
+
+/// Set the [`system`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-system) setting.
+///
+/// The system has the form `cpu-os`, e.g. `x86_64-linux`.
+pub fn set_system(system: &str) -> Result<()> {
+    validate_system(system)?;
+    set("system", system)
+}
+
+/// Get the [`extra-platforms`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-extra-platforms) setting.
+pub fn extra_platforms() -> Result<BTreeSet<String>> {
+    Ok(get_list("extra-platforms")?.into_iter().collect())
+}
+
+/// Set the [`extra-platforms`](https://nix.dev/manual/nix/latest/command-ref/conf-file.html#conf-extra-platforms) setting.
+pub fn set_extra_platforms<'a>(platforms: impl IntoIterator<Item = &'a str>) -> Result<()> {
+    let platforms: Vec<&str> = platforms.into_iter().collect();
+    for platform in &platforms {
+        validate_system(platform)?;
+    }
+    set_list("extra-platforms", platforms)
+}
+
+fn validate_system(system: &str) -> Result<()> {
+    match system.split_once('-') {
+        Some((cpu, os))
+            if !cpu.is_empty() && !os.is_empty() && !system.contains(char::is_whitespace) =>
+        {
+            Ok(())
+        }
+        _ => bail!("invalid system {:?}, expected e.g. x86_64-linux", system),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use crate::check_call;
@@ -101,4 +312,69 @@ mod tests {
 
         assert_eq!(res, new_value);
     }
+
+    const CHILD_PROCESS_VAR: &str = "NIX_BINDINGS_UTIL_TEST_CHILD_PROCESS";
+
+    /// Runs the test `name` again in a child process.
+    ///
+    /// Returns `true` in the child process, where the test should do its work, and `false` in the parent, after the child succeeded.
+    /// This way, the global settings that the test changes are not observed by other tests running in this process.
+    fn in_child_process(name: &str) -> bool {
+        if std::env::var_os(CHILD_PROCESS_VAR).is_some() {
+            return true;
+        }
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args([name, "--exact", "--test-threads=1"])
+            .env(CHILD_PROCESS_VAR, "1")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(
+            output.status.success() && stdout.contains("1 passed"),
+            "{}\n{}",
+            stdout,
+            String::from_utf8_lossy(&output.stderr)
+        );
+        false
+    }
+
+    #[test]
+    fn typed_settings() {
+        if !in_child_process("settings::tests::typed_settings") {
+            return;
+        }
+        set_cores(3).unwrap();
+        assert_eq!(cores().unwrap(), 3);
+        assert_eq!(get("cores").unwrap(), "3");
+
+        let key = "cache.nixos.org-1:6NCHdD59X431o0gWypbMrAURkbJ16ZPMQFGspcDShjY=";
+        set_trusted_public_keys([key, "foo:YWJj"]).unwrap();
+        assert_eq!(trusted_public_keys().unwrap(), [key, "foo:YWJj"]);
+        assert!(set_trusted_public_keys(["no-colon"]).is_err());
+        assert!(set_trusted_public_keys(["foo:not base64"]).is_err());
+
+        set_extra_platforms(["i686-linux", "aarch64-linux"]).unwrap();
+        assert_eq!(
+            extra_platforms().unwrap(),
+            BTreeSet::from(["aarch64-linux".to_owned(), "i686-linux".to_owned()])
+        );
+        assert!(set_extra_platforms(["linux"]).is_err());
+
+        set_max_jobs(MaxJobs::Count(5)).unwrap();
+        assert_eq!(max_jobs().unwrap(), MaxJobs::Count(5));
+        set_max_jobs(MaxJobs::Auto).unwrap();
+        assert!(matches!(max_jobs().unwrap(), MaxJobs::Count(n) if n >= 1));
+
+        assert!(system().unwrap().contains('-'));
+        assert!(set_system("").is_err());
+        sandbox().unwrap();
+        experimental_features().unwrap();
+    }
+
+    #[test]
+    fn set_substituters_validates() {
+        assert!(set_substituters(["cache.nixos.org"]).is_err());
+        assert!(set_substituters(["https://"]).is_err());
+        assert!(set_substituters(["https://a b"]).is_err());
+    }
 }
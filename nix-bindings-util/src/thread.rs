@@ -0,0 +1,48 @@
+use anyhow::Result;
+use std::any::Any;
+use std::sync::OnceLock;
+
+/// Prepares the current thread for calling into Nix, and returns a guard that undoes the preparation when dropped.
+pub type ThreadSetup = fn() -> Result<Box<dyn Any>>;
+
+static THREAD_SETUP: OnceLock<ThreadSetup> = OnceLock::new();
+
+/// Set how threads that the bindings start themselves, such as the one of `Store::realise_in_background`, are prepared.
+///
+/// `nix_bindings_expr` sets this when it is initialized, so that those threads are registered with the garbage collector,
+/// without the crates that start them depending on the collector. Only the first call has an effect.
+pub fn set_thread_setup(setup: ThreadSetup) {
+    let _ = THREAD_SETUP.set(setup);
+}
+
+/// Prepare the current thread with the function passed to [`set_thread_setup`], if any.
+///
+/// Keep the returned guard alive for as long as the thread calls into Nix.
+pub fn setup_current_thread() -> Result<Option<Box<dyn Any>>> {
+    THREAD_SETUP.get().map(|setup| setup()).transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static SETUPS: AtomicUsize = AtomicUsize::new(0);
+
+    #[test]
+    fn setup() {
+        set_thread_setup(|| {
+            SETUPS.fetch_add(1, Ordering::SeqCst);
+            Ok(Box::new(()))
+        });
+        // Ignored, because the setup was set already
+        set_thread_setup(|| anyhow::bail!("second setup"));
+        std::thread::spawn(|| {
+            let guard = setup_current_thread().unwrap();
+            assert!(guard.is_some());
+        })
+        .join()
+        .unwrap();
+        assert_eq!(SETUPS.load(Ordering::SeqCst), 1);
+    }
+}
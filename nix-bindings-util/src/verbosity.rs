@@ -0,0 +1,123 @@
+use anyhow::Result;
+use nix_bindings_util_sys as raw;
+use std::sync::Mutex;
+
+use crate::{check_call, context};
+
+/// How much Nix logs, from least to most.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Verbosity {
+    Error,
+    Warn,
+    Notice,
+    Info,
+    Talkative,
+    Chatty,
+    Debug,
+    Vomit,
+}
+
+impl Verbosity {
+    fn to_raw(self) -> raw::verbosity {
+        match self {
+            Verbosity::Error => raw::verbosity_NIX_LVL_ERROR,
+            Verbosity::Warn => raw::verbosity_NIX_LVL_WARN,
+            Verbosity::Notice => raw::verbosity_NIX_LVL_NOTICE,
+            Verbosity::Info => raw::verbosity_NIX_LVL_INFO,
+            Verbosity::Talkative => raw::verbosity_NIX_LVL_TALKATIVE,
+            Verbosity::Chatty => raw::verbosity_NIX_LVL_CHATTY,
+            Verbosity::Debug => raw::verbosity_NIX_LVL_DEBUG,
+            Verbosity::Vomit => raw::verbosity_NIX_LVL_VOMIT,
+        }
+    }
+}
+
+/// The last level set with [`set_verbosity`]. Nix starts out at [`Verbosity::Info`], the default of `verbosity` in libutil.
+static VERBOSITY: Mutex<Verbosity> = Mutex::new(Verbosity::Info);
+
+/// Set the verbosity of Nix's logger. This is global to the process.
+///
+/// Nix keeps logging to its own logger: the C API can not install a logger, so log messages and activities can not be routed into the `log` or `tracing` crates.
+#[doc(alias = "nix_set_verbosity")]
+pub fn set_verbosity(level: Verbosity) -> Result<()> {
+    let mut verbosity = VERBOSITY.lock().unwrap();
+    let mut ctx = context::Context::new();
+    unsafe {
+        check_call!(raw::set_verbosity(&mut ctx, level.to_raw()))?;
+    }
+    *verbosity = level;
+    Ok(())
+}
+
+/// Get the verbosity of Nix's logger.
+///
+/// The C API can not read the verbosity, so this returns the level that was last set with [`set_verbosity`], or Nix's default, [`Verbosity::Info`].
+/// Changes made by other means, such as the `nix` command line, are not reflected.
+pub fn get_verbosity() -> Verbosity {
+    *VERBOSITY.lock().unwrap()
+}
+
+/// Restores the previous verbosity when dropped.
+///
+/// Guards should be dropped in the reverse order of their creation. As the verbosity is global, this also holds across threads.
+///
+/// # Examples
+///
+/// ```no_run
+/// # use nix_bindings_util::verbosity::{Verbosity, VerbosityGuard};
+/// # fn example() -> anyhow::Result<()> {
+/// {
+///     let _guard = VerbosityGuard::new(Verbosity::Debug)?;
+///     // ... evaluate something, with debug logging
+/// }
+/// // back to the previous verbosity
+/// # Ok(())
+/// # }
+/// ```
+#[must_use = "the previous verbosity is restored when the guard is dropped"]
+pub struct VerbosityGuard {
+    previous: Verbosity,
+}
+
+impl VerbosityGuard {
+    /// Set the verbosity to `level`, until the guard is dropped.
+    pub fn new(level: Verbosity) -> Result<VerbosityGuard> {
+        let previous = get_verbosity();
+        set_verbosity(level)?;
+        Ok(VerbosityGuard { previous })
+    }
+}
+
+impl Drop for VerbosityGuard {
+    fn drop(&mut self) {
+        // Only fails if the C API is broken, and we can't report it from here
+        let _ = set_verbosity(self.previous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_get_guard() {
+        set_verbosity(Verbosity::Warn).unwrap();
+        assert_eq!(get_verbosity(), Verbosity::Warn);
+        {
+            let _guard = VerbosityGuard::new(Verbosity::Vomit).unwrap();
+            assert_eq!(get_verbosity(), Verbosity::Vomit);
+            {
+                let _guard = VerbosityGuard::new(Verbosity::Error).unwrap();
+                assert_eq!(get_verbosity(), Verbosity::Error);
+            }
+            assert_eq!(get_verbosity(), Verbosity::Vomit);
+        }
+        assert_eq!(get_verbosity(), Verbosity::Warn);
+    }
+
+    #[test]
+    fn ordering() {
+        assert!(Verbosity::Error < Verbosity::Info);
+        assert!(Verbosity::Debug < Verbosity::Vomit);
+    }
+}